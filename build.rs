@@ -0,0 +1,22 @@
+//! Allows linking test binaries on hosts without `libohinputmethod`.
+//!
+//! `ohos-ime-sys` unconditionally links `libohinputmethod`, which only exists on OpenHarmony.
+//! When building for other targets we provide an empty archive with that name. Tests and the
+//! `testing` feature use the fake backend in `src/ffi/fake.rs`, so no symbols from the library are
+//! actually referenced.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    let target_family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    if target_env == "ohos" || target_family != "unix" {
+        return;
+    }
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR not set"));
+    std::fs::write(out_dir.join("libohinputmethod.a"), b"!<arch>\n")
+        .expect("Failed to write stub libohinputmethod.a");
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+}
@@ -0,0 +1,187 @@
+//! A common error type for this crate.
+
+use crate::{
+    ApplyTextConfigError, CreateImeProxyError, CreateTextEditorProxyError,
+    CreateTextEditorProxyErrorKind, IndexError, InvalidSelection, PrivateCommandError,
+};
+use ohos_ime_sys::types::InputMethodErrorCode;
+use std::fmt::{Display, Formatter};
+
+/// Any error returned by this crate.
+///
+/// All the specific error types of this crate convert into `Error`. Unlike
+/// [`CreateImeProxyError`] and [`CreateTextEditorProxyError`], it does not carry the resources
+/// passed to the failed call, so it is `Send + Sync` and can be used with generic error handling
+/// libraries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A call to the inputmethod API failed.
+    InputMethod(InputMethodErrorCode),
+    /// Attaching to the input method failed, see [`ImeProxy::new()`](crate::ImeProxy::new).
+    Attach(InputMethodErrorCode),
+    /// See [`RawTextEditorProxy::new()`](crate::RawTextEditorProxy::new).
+    CreateTextEditorProxy(CreateTextEditorProxyErrorKind),
+    ApplyTextConfig(ApplyTextConfigError),
+    PrivateCommand(PrivateCommandError),
+    InvalidSelection(InvalidSelection),
+    Index(IndexError),
+}
+
+/// Displays an [`InputMethodErrorCode`] with its name, if known.
+pub(crate) struct ErrorCode(pub(crate) InputMethodErrorCode);
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self.0 {
+            InputMethodErrorCode::UNDEFINED => "undefined",
+            InputMethodErrorCode::PARAMCHECK => "parameter check failed",
+            InputMethodErrorCode::PACKAGEMANAGER => "bundle manager error",
+            InputMethodErrorCode::IMENGINE => "input method engine error",
+            InputMethodErrorCode::IMCLIENT => "input method client error",
+            InputMethodErrorCode::CONFIG_PERSIST => "configuration persistence error",
+            InputMethodErrorCode::CONTROLLER => "input method controller error",
+            InputMethodErrorCode::SETTINGS => "input method setting error",
+            InputMethodErrorCode::IMMS => "input method manager service error",
+            InputMethodErrorCode::DETACHED => "input method client detached",
+            InputMethodErrorCode::NULL_POINTER => "unexpected null pointer",
+            InputMethodErrorCode::QUERY_FAILED => "query failed",
+            _ => "unknown error",
+        };
+        write!(f, "{name} (error code {})", self.0 .0)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InputMethod(code) => {
+                write!(f, "inputmethod API call failed: {}", ErrorCode(*code))
+            }
+            Error::Attach(code) => write!(
+                f,
+                "attaching to the input method failed: {}",
+                ErrorCode(*code)
+            ),
+            // The wrapped errors are reported via `source()`.
+            Error::CreateTextEditorProxy(_) => f.write_str("creating the text editor failed"),
+            Error::ApplyTextConfig(_) => f.write_str("applying the text config failed"),
+            Error::PrivateCommand(_) => f.write_str("private command failed"),
+            Error::InvalidSelection(_) => f.write_str("creating the text selection failed"),
+            Error::Index(_) => f.write_str("converting a text index failed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InputMethod(_) | Error::Attach(_) => None,
+            Error::CreateTextEditorProxy(e) => Some(e),
+            Error::ApplyTextConfig(e) => Some(e),
+            Error::PrivateCommand(e) => Some(e),
+            Error::InvalidSelection(e) => Some(e),
+            Error::Index(e) => Some(e),
+        }
+    }
+}
+
+impl From<InputMethodErrorCode> for Error {
+    fn from(code: InputMethodErrorCode) -> Self {
+        Error::InputMethod(code)
+    }
+}
+
+impl From<CreateImeProxyError> for Error {
+    fn from(error: CreateImeProxyError) -> Self {
+        Error::Attach(error.error_code)
+    }
+}
+
+impl From<CreateTextEditorProxyError> for Error {
+    fn from(error: CreateTextEditorProxyError) -> Self {
+        Error::CreateTextEditorProxy(error.reason)
+    }
+}
+
+impl From<CreateTextEditorProxyErrorKind> for Error {
+    fn from(kind: CreateTextEditorProxyErrorKind) -> Self {
+        Error::CreateTextEditorProxy(kind)
+    }
+}
+
+impl From<ApplyTextConfigError> for Error {
+    fn from(error: ApplyTextConfigError) -> Self {
+        Error::ApplyTextConfig(error)
+    }
+}
+
+impl From<PrivateCommandError> for Error {
+    fn from(error: PrivateCommandError) -> Self {
+        Error::PrivateCommand(error)
+    }
+}
+
+impl From<InvalidSelection> for Error {
+    fn from(error: InvalidSelection) -> Self {
+        Error::InvalidSelection(error)
+    }
+}
+
+impl From<IndexError> for Error {
+    fn from(error: IndexError) -> Self {
+        Error::Index(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::RecordingIme;
+    use crate::RawTextEditorProxy;
+    use std::error::Error as _;
+
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+    #[test]
+    fn errors_convert_and_display() {
+        assert_send_sync::<Error>();
+
+        let error = Error::from(InputMethodErrorCode::DETACHED);
+        assert_eq!(
+            error.to_string(),
+            "inputmethod API call failed: input method client detached (error code 12800009)"
+        );
+        assert!(error.source().is_none());
+
+        let error = Error::from(ApplyTextConfigError::SetWindowIdFailed(
+            InputMethodErrorCode::PARAMCHECK,
+        ));
+        assert_eq!(error.to_string(), "applying the text config failed");
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "setting the window id of the text config failed: parameter check failed \
+             (error code 401)"
+        );
+
+        crate::ffi::fake::inject_error(
+            "OH_TextEditorProxy_SetGetTextConfigFunc",
+            InputMethodErrorCode::UNDEFINED,
+        );
+        let Err(create_error) = RawTextEditorProxy::new(Box::new(RecordingIme::default())) else {
+            panic!("Creating the text editor should fail");
+        };
+        let error = Error::from(create_error);
+        assert_eq!(
+            error,
+            Error::CreateTextEditorProxy(CreateTextEditorProxyErrorKind::RegisterCallbacksFailed(
+                InputMethodErrorCode::UNDEFINED
+            ))
+        );
+        assert_eq!(error.to_string(), "creating the text editor failed");
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "registering the text editor callbacks failed: undefined (error code 1)"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+//! Indirection over the raw inputmethod API.
+//!
+//! Outside of tests this simply re-exports the modules of `ohos_ime_sys`. Tests (and the `testing`
+//! feature) use an in-memory fake with the same function signatures instead, so that the dispatch
+//! logic and the C callbacks can be exercised on hosts without the inputmethod service.
+
+#[cfg(not(any(test, feature = "testing")))]
+pub(crate) use ohos_ime_sys::{
+    attach_options, controller, cursor_info, inputmethod_proxy, private_command, text_avoid_info,
+    text_config, text_editor_proxy,
+};
+
+#[cfg(any(test, feature = "testing"))]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) mod fake;
+#[cfg(any(test, feature = "testing"))]
+pub(crate) use fake::{
+    attach_options, controller, cursor_info, inputmethod_proxy, private_command, text_avoid_info,
+    text_config, text_editor_proxy,
+};
@@ -0,0 +1,710 @@
+//! In-memory fake of the inputmethod API.
+//!
+//! Every function has the same signature as its counterpart in `ohos_ime_sys`. Objects are plain
+//! heap allocations behind the opaque pointer types. Calls are recorded per thread and can be
+//! inspected with [`take_calls()`], and errors can be injected with [`inject_error()`].
+#![allow(non_snake_case)]
+
+use ohos_ime_sys::types::{InputMethodErrorCode, InputMethodResult};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static CALLS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+    static INJECTED_ERRORS: RefCell<HashMap<&'static str, InputMethodErrorCode>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Return (and clear) the names of all fake API functions called on the current thread.
+pub(crate) fn take_calls() -> Vec<&'static str> {
+    CALLS.with_borrow_mut(std::mem::take)
+}
+
+/// Make the next call to `function` on the current thread fail with `code`.
+///
+/// Functions returning a pointer return a null pointer instead.
+pub(crate) fn inject_error(function: &'static str, code: InputMethodErrorCode) {
+    INJECTED_ERRORS.with_borrow_mut(|errors| errors.insert(function, code));
+}
+
+/// Record a call to `function` and return the injected error, if any.
+fn record(function: &'static str) -> InputMethodResult {
+    CALLS.with_borrow_mut(|calls| calls.push(function));
+    match INJECTED_ERRORS.with_borrow_mut(|errors| errors.remove(function)) {
+        Some(code) => Err(code),
+        None => Ok(()),
+    }
+}
+
+fn alloc<T, R>(value: T) -> *mut R {
+    Box::into_raw(Box::new(value)).cast()
+}
+
+/// # Safety
+///
+/// `ptr` must have been returned by `alloc::<T, _>` and not been freed yet.
+unsafe fn free<T, R>(ptr: *mut R) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr.cast::<T>()));
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null, or have been returned by `alloc::<T, _>` and not been freed yet.
+unsafe fn get<'a, T, R>(ptr: *mut R) -> Result<&'a mut T, InputMethodErrorCode> {
+    ptr.cast::<T>()
+        .as_mut()
+        .ok_or(InputMethodErrorCode::NULL_POINTER)
+}
+
+/// Write `value` to the out pointer `out`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn write_out<T>(out: *mut T, value: T) -> InputMethodResult {
+    if out.is_null() {
+        return Err(InputMethodErrorCode::NULL_POINTER);
+    }
+    out.write(value);
+    Ok(())
+}
+
+pub(crate) mod attach_options {
+    use super::{alloc, free, get, record, write_out};
+    pub(crate) use ohos_ime_sys::attach_options::InputMethod_AttachOptions;
+    use ohos_ime_sys::types::InputMethodResult;
+
+    #[derive(Debug)]
+    pub(crate) struct FakeAttachOptions {
+        pub(crate) show_keyboard: bool,
+    }
+
+    pub(crate) unsafe fn OH_AttachOptions_Create(
+        showKeyboard: bool,
+    ) -> *mut InputMethod_AttachOptions {
+        if record("OH_AttachOptions_Create").is_err() {
+            return core::ptr::null_mut();
+        }
+        alloc(FakeAttachOptions {
+            show_keyboard: showKeyboard,
+        })
+    }
+
+    pub(crate) unsafe fn OH_AttachOptions_Destroy(options: *mut InputMethod_AttachOptions) {
+        let _ = record("OH_AttachOptions_Destroy");
+        free::<FakeAttachOptions, _>(options);
+    }
+
+    pub(crate) unsafe fn OH_AttachOptions_IsShowKeyboard(
+        options: *mut InputMethod_AttachOptions,
+        showKeyboard: *mut bool,
+    ) -> InputMethodResult {
+        record("OH_AttachOptions_IsShowKeyboard")?;
+        let options = get::<FakeAttachOptions, _>(options)?;
+        write_out(showKeyboard, options.show_keyboard)
+    }
+}
+
+pub(crate) mod controller {
+    use super::attach_options::FakeAttachOptions;
+    use super::inputmethod_proxy::FakeInputMethodProxy;
+    use super::text_config::FakeTextConfig;
+    use super::text_editor_proxy::FakeTextEditorProxy;
+    use super::{alloc, free, get, record, write_out};
+    use ohos_ime_sys::attach_options::InputMethod_AttachOptions;
+    use ohos_ime_sys::inputmethod_proxy::InputMethod_InputMethodProxy;
+    use ohos_ime_sys::text_editor_proxy::InputMethod_TextEditorProxy;
+    use ohos_ime_sys::types::InputMethodResult;
+
+    /// Attach the text editor, querying its text config like the real input method does.
+    pub(crate) unsafe fn OH_InputMethodController_Attach(
+        textEditorProxy: *mut InputMethod_TextEditorProxy,
+        options: *mut InputMethod_AttachOptions,
+        inputMethodProxy: *mut *mut InputMethod_InputMethodProxy,
+    ) -> InputMethodResult {
+        record("OH_InputMethodController_Attach")?;
+        let editor = *get::<FakeTextEditorProxy, _>(textEditorProxy)?;
+        let options = get::<FakeAttachOptions, _>(options)?;
+        let mut config = FakeTextConfig::default();
+        if let Some(get_text_config) = editor.get_text_config {
+            get_text_config(textEditorProxy, (&raw mut config).cast());
+        }
+        let proxy = FakeInputMethodProxy {
+            keyboard_shown: options.show_keyboard,
+            text_config: config,
+            ..FakeInputMethodProxy::default()
+        };
+        write_out(inputMethodProxy, alloc(proxy))
+    }
+
+    pub(crate) unsafe fn OH_InputMethodController_Detach(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+    ) -> InputMethodResult {
+        record("OH_InputMethodController_Detach")?;
+        get::<FakeInputMethodProxy, _>(inputMethodProxy)?;
+        free::<FakeInputMethodProxy, _>(inputMethodProxy);
+        Ok(())
+    }
+}
+
+pub(crate) mod cursor_info {
+    use super::{alloc, free, record};
+    pub(crate) use ohos_ime_sys::cursor_info::InputMethod_CursorInfo;
+
+    #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    pub(crate) struct FakeCursorInfo {
+        pub(crate) left: f64,
+        pub(crate) top: f64,
+        pub(crate) width: f64,
+        pub(crate) height: f64,
+    }
+
+    pub(crate) unsafe fn OH_CursorInfo_Create(
+        left: f64,
+        top: f64,
+        width: f64,
+        height: f64,
+    ) -> *mut InputMethod_CursorInfo {
+        if record("OH_CursorInfo_Create").is_err() {
+            return core::ptr::null_mut();
+        }
+        alloc(FakeCursorInfo {
+            left,
+            top,
+            width,
+            height,
+        })
+    }
+
+    pub(crate) unsafe fn OH_CursorInfo_Destroy(cursorInfo: *mut InputMethod_CursorInfo) {
+        let _ = record("OH_CursorInfo_Destroy");
+        free::<FakeCursorInfo, _>(cursorInfo);
+    }
+}
+
+pub(crate) mod inputmethod_proxy {
+    use super::cursor_info::FakeCursorInfo;
+    use super::private_command::FakePrivateCommand;
+    use super::text_config::FakeTextConfig;
+    use super::{get, record};
+    use ohos_ime_sys::cursor_info::InputMethod_CursorInfo;
+    pub(crate) use ohos_ime_sys::inputmethod_proxy::InputMethod_InputMethodProxy;
+    use ohos_ime_sys::private_command::InputMethod_PrivateCommand;
+    use ohos_ime_sys::types::{
+        InputMethodErrorCode, InputMethodResult, InputMethod_EnterKeyType,
+        InputMethod_TextInputType,
+    };
+
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct FakeInputMethodProxy {
+        pub(crate) keyboard_shown: bool,
+        /// The text config queried from the text editor when attaching.
+        pub(crate) text_config: FakeTextConfig,
+        /// Text (utf-16) and selection of the last `NotifySelectionChange`.
+        pub(crate) selection_change: Option<(Vec<u16>, i32, i32)>,
+        /// Enter key and input type of the last `NotifyConfigurationChange`.
+        pub(crate) configuration_change:
+            Option<(InputMethod_EnterKeyType, InputMethod_TextInputType)>,
+        /// Cursor of the last `NotifyCursorUpdate`.
+        pub(crate) cursor_info: Option<FakeCursorInfo>,
+        /// All private commands sent so far.
+        pub(crate) private_commands: Vec<FakePrivateCommand>,
+    }
+
+    /// Return a snapshot of the state of a fake input method proxy.
+    ///
+    /// # Safety
+    ///
+    /// `proxy` must be a live proxy returned by the fake `OH_InputMethodController_Attach`.
+    pub(crate) unsafe fn fake_proxy(
+        proxy: *mut InputMethod_InputMethodProxy,
+    ) -> FakeInputMethodProxy {
+        get::<FakeInputMethodProxy, _>(proxy)
+            .expect("null InputMethodProxy")
+            .clone()
+    }
+
+    pub(crate) unsafe fn OH_InputMethodProxy_ShowKeyboard(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+    ) -> InputMethodResult {
+        record("OH_InputMethodProxy_ShowKeyboard")?;
+        get::<FakeInputMethodProxy, _>(inputMethodProxy)?.keyboard_shown = true;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_InputMethodProxy_HideKeyboard(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+    ) -> InputMethodResult {
+        record("OH_InputMethodProxy_HideKeyboard")?;
+        get::<FakeInputMethodProxy, _>(inputMethodProxy)?.keyboard_shown = false;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_InputMethodProxy_NotifySelectionChange(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+        text: *mut u16,
+        length: usize,
+        start: ::core::ffi::c_int,
+        end: ::core::ffi::c_int,
+    ) -> InputMethodResult {
+        record("OH_InputMethodProxy_NotifySelectionChange")?;
+        let proxy = get::<FakeInputMethodProxy, _>(inputMethodProxy)?;
+        let text = if length == 0 {
+            Vec::new()
+        } else if text.is_null() {
+            return Err(InputMethodErrorCode::NULL_POINTER);
+        } else {
+            core::slice::from_raw_parts(text, length).to_vec()
+        };
+        proxy.selection_change = Some((text, start, end));
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_InputMethodProxy_NotifyConfigurationChange(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+        enterKey: InputMethod_EnterKeyType,
+        textType: InputMethod_TextInputType,
+    ) -> InputMethodResult {
+        record("OH_InputMethodProxy_NotifyConfigurationChange")?;
+        get::<FakeInputMethodProxy, _>(inputMethodProxy)?.configuration_change =
+            Some((enterKey, textType));
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_InputMethodProxy_NotifyCursorUpdate(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+        cursorInfo: *mut InputMethod_CursorInfo,
+    ) -> InputMethodResult {
+        record("OH_InputMethodProxy_NotifyCursorUpdate")?;
+        let proxy = get::<FakeInputMethodProxy, _>(inputMethodProxy)?;
+        proxy.cursor_info = Some(*get::<FakeCursorInfo, _>(cursorInfo)?);
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_InputMethodProxy_SendPrivateCommand(
+        inputMethodProxy: *mut InputMethod_InputMethodProxy,
+        privateCommand: *mut *mut InputMethod_PrivateCommand,
+        size: usize,
+    ) -> InputMethodResult {
+        record("OH_InputMethodProxy_SendPrivateCommand")?;
+        let proxy = get::<FakeInputMethodProxy, _>(inputMethodProxy)?;
+        if size == 0 {
+            return Ok(());
+        }
+        if privateCommand.is_null() {
+            return Err(InputMethodErrorCode::NULL_POINTER);
+        }
+        for &command in core::slice::from_raw_parts(privateCommand, size) {
+            let command = get::<FakePrivateCommand, _>(command)?;
+            proxy.private_commands.push(command.clone());
+        }
+        Ok(())
+    }
+}
+
+pub(crate) mod private_command {
+    use super::{alloc, free, get, record, write_out};
+    pub(crate) use ohos_ime_sys::private_command::InputMethod_PrivateCommand;
+    use ohos_ime_sys::types::{
+        InputMethodErrorCode, InputMethodResult, InputMethod_CommandValueType,
+    };
+    use std::ffi::c_char;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub(crate) enum FakeCommandValue {
+        None,
+        Bool(bool),
+        Int(i32),
+        Str(Vec<u8>),
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub(crate) struct FakePrivateCommand {
+        pub(crate) key: Vec<u8>,
+        pub(crate) value: FakeCommandValue,
+    }
+
+    impl FakePrivateCommand {
+        /// Allocate a copy of this command, e.g. to pass it to `receive_private_command`.
+        pub(crate) fn to_raw(&self) -> *mut InputMethod_PrivateCommand {
+            alloc(self.clone())
+        }
+    }
+
+    unsafe fn bytes(ptr: *mut c_char, len: usize) -> Result<Vec<u8>, InputMethodErrorCode> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        if ptr.is_null() {
+            return Err(InputMethodErrorCode::NULL_POINTER);
+        }
+        Ok(core::slice::from_raw_parts(ptr.cast::<u8>(), len).to_vec())
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_Create(
+        key: *mut c_char,
+        keyLength: usize,
+    ) -> *mut InputMethod_PrivateCommand {
+        if record("OH_PrivateCommand_Create").is_err() {
+            return core::ptr::null_mut();
+        }
+        let Ok(key) = bytes(key, keyLength) else {
+            return core::ptr::null_mut();
+        };
+        alloc(FakePrivateCommand {
+            key,
+            value: FakeCommandValue::None,
+        })
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_Destroy(command: *mut InputMethod_PrivateCommand) {
+        let _ = record("OH_PrivateCommand_Destroy");
+        free::<FakePrivateCommand, _>(command);
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_SetBoolValue(
+        command: *mut InputMethod_PrivateCommand,
+        value: bool,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_SetBoolValue")?;
+        get::<FakePrivateCommand, _>(command)?.value = FakeCommandValue::Bool(value);
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_SetIntValue(
+        command: *mut InputMethod_PrivateCommand,
+        value: i32,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_SetIntValue")?;
+        get::<FakePrivateCommand, _>(command)?.value = FakeCommandValue::Int(value);
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_SetStrValue(
+        command: *mut InputMethod_PrivateCommand,
+        value: *mut c_char,
+        valueLength: usize,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_SetStrValue")?;
+        let command = get::<FakePrivateCommand, _>(command)?;
+        command.value = FakeCommandValue::Str(bytes(value, valueLength)?);
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_GetKey(
+        command: *mut InputMethod_PrivateCommand,
+        key: *mut *const c_char,
+        keyLength: *mut usize,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_GetKey")?;
+        let command = get::<FakePrivateCommand, _>(command)?;
+        write_out(key, command.key.as_ptr().cast())?;
+        write_out(keyLength, command.key.len())
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_GetValueType(
+        command: *mut InputMethod_PrivateCommand,
+        type_: *mut InputMethod_CommandValueType,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_GetValueType")?;
+        let value_type = match get::<FakePrivateCommand, _>(command)?.value {
+            FakeCommandValue::None => InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_NONE,
+            FakeCommandValue::Bool(_) => InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_BOOL,
+            FakeCommandValue::Int(_) => InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_INT32,
+            FakeCommandValue::Str(_) => InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_STRING,
+        };
+        write_out(type_, value_type)
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_GetBoolValue(
+        command: *mut InputMethod_PrivateCommand,
+        value: *mut bool,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_GetBoolValue")?;
+        match get::<FakePrivateCommand, _>(command)?.value {
+            FakeCommandValue::Bool(v) => write_out(value, v),
+            _ => Err(InputMethodErrorCode::PARAMCHECK),
+        }
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_GetIntValue(
+        command: *mut InputMethod_PrivateCommand,
+        value: *mut i32,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_GetIntValue")?;
+        match get::<FakePrivateCommand, _>(command)?.value {
+            FakeCommandValue::Int(v) => write_out(value, v),
+            _ => Err(InputMethodErrorCode::PARAMCHECK),
+        }
+    }
+
+    pub(crate) unsafe fn OH_PrivateCommand_GetStrValue(
+        command: *mut InputMethod_PrivateCommand,
+        value: *mut *const c_char,
+        valueLength: *mut usize,
+    ) -> InputMethodResult {
+        record("OH_PrivateCommand_GetStrValue")?;
+        match &get::<FakePrivateCommand, _>(command)?.value {
+            FakeCommandValue::Str(v) => {
+                write_out(value, v.as_ptr().cast())?;
+                write_out(valueLength, v.len())
+            }
+            _ => Err(InputMethodErrorCode::PARAMCHECK),
+        }
+    }
+}
+
+pub(crate) mod text_avoid_info {
+    use super::{get, record};
+    pub(crate) use ohos_ime_sys::text_avoid_info::InputMethod_TextAvoidInfo;
+    use ohos_ime_sys::types::InputMethodResult;
+
+    #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    pub(crate) struct FakeTextAvoidInfo {
+        pub(crate) position_y: f64,
+        pub(crate) height: f64,
+    }
+
+    pub(crate) unsafe fn OH_TextAvoidInfo_SetPositionY(
+        info: *mut InputMethod_TextAvoidInfo,
+        positionY: f64,
+    ) -> InputMethodResult {
+        record("OH_TextAvoidInfo_SetPositionY")?;
+        get::<FakeTextAvoidInfo, _>(info)?.position_y = positionY;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_TextAvoidInfo_SetHeight(
+        info: *mut InputMethod_TextAvoidInfo,
+        height: f64,
+    ) -> InputMethodResult {
+        record("OH_TextAvoidInfo_SetHeight")?;
+        get::<FakeTextAvoidInfo, _>(info)?.height = height;
+        Ok(())
+    }
+}
+
+pub(crate) mod text_config {
+    use super::text_avoid_info::{FakeTextAvoidInfo, InputMethod_TextAvoidInfo};
+    use super::{get, record, write_out};
+    pub(crate) use ohos_ime_sys::text_config::InputMethod_TextConfig;
+    use ohos_ime_sys::types::{
+        InputMethodResult, InputMethod_EnterKeyType, InputMethod_TextInputType,
+    };
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub(crate) struct FakeTextConfig {
+        pub(crate) input_type: InputMethod_TextInputType,
+        pub(crate) enter_key_type: InputMethod_EnterKeyType,
+        pub(crate) preview_text_support: bool,
+        pub(crate) selection: (i32, i32),
+        pub(crate) window_id: i32,
+        /// Owned by the config, like in the real API.
+        pub(crate) avoid_info: FakeTextAvoidInfo,
+        pub(crate) placeholder: String,
+        pub(crate) ability_name: String,
+    }
+
+    impl Default for FakeTextConfig {
+        fn default() -> Self {
+            Self {
+                input_type: InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NONE,
+                enter_key_type: InputMethod_EnterKeyType::IME_ENTER_KEY_UNSPECIFIED,
+                preview_text_support: false,
+                selection: (0, 0),
+                window_id: 0,
+                avoid_info: FakeTextAvoidInfo::default(),
+                placeholder: String::new(),
+                ability_name: String::new(),
+            }
+        }
+    }
+
+    pub(crate) unsafe fn OH_TextConfig_SetInputType(
+        config: *mut InputMethod_TextConfig,
+        inputType: InputMethod_TextInputType,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetInputType")?;
+        get::<FakeTextConfig, _>(config)?.input_type = inputType;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_TextConfig_SetEnterKeyType(
+        config: *mut InputMethod_TextConfig,
+        enterKeyType: InputMethod_EnterKeyType,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetEnterKeyType")?;
+        get::<FakeTextConfig, _>(config)?.enter_key_type = enterKeyType;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_TextConfig_SetPreviewTextSupport(
+        config: *mut InputMethod_TextConfig,
+        supported: bool,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetPreviewTextSupport")?;
+        get::<FakeTextConfig, _>(config)?.preview_text_support = supported;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_TextConfig_SetSelection(
+        config: *mut InputMethod_TextConfig,
+        start: i32,
+        end: i32,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetSelection")?;
+        get::<FakeTextConfig, _>(config)?.selection = (start, end);
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_TextConfig_SetWindowId(
+        config: *mut InputMethod_TextConfig,
+        windowId: i32,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetWindowId")?;
+        get::<FakeTextConfig, _>(config)?.window_id = windowId;
+        Ok(())
+    }
+
+    pub(crate) unsafe fn OH_TextConfig_GetTextAvoidInfo(
+        config: *mut InputMethod_TextConfig,
+        avoidInfo: *mut *mut InputMethod_TextAvoidInfo,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_GetTextAvoidInfo")?;
+        let avoid_info = &raw mut get::<FakeTextConfig, _>(config)?.avoid_info;
+        write_out(avoidInfo, avoid_info.cast())
+    }
+
+    /// Decode a NUL terminated utf-16 string with `length` code units, including the NUL.
+    ///
+    /// # Safety
+    ///
+    /// `text` must be null or valid for reads of `length` code units.
+    #[cfg(feature = "api-20")]
+    unsafe fn read_utf16_with_nul(text: *const u16, length: usize) -> String {
+        if text.is_null() || length == 0 {
+            return String::new();
+        }
+        let text = std::slice::from_raw_parts(text, length);
+        assert_eq!(text.last(), Some(&0), "string must be NUL terminated");
+        String::from_utf16(&text[..length - 1]).expect("invalid utf-16")
+    }
+
+    #[cfg(feature = "api-20")]
+    pub(crate) unsafe fn OH_TextConfig_SetPlaceholder(
+        config: *mut InputMethod_TextConfig,
+        placeholder: *const u16,
+        length: usize,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetPlaceholder")?;
+        assert!(length <= 256, "placeholder too long");
+        get::<FakeTextConfig, _>(config)?.placeholder = read_utf16_with_nul(placeholder, length);
+        Ok(())
+    }
+
+    #[cfg(feature = "api-20")]
+    pub(crate) unsafe fn OH_TextConfig_SetAbilityName(
+        config: *mut InputMethod_TextConfig,
+        abilityName: *const u16,
+        length: usize,
+    ) -> InputMethodResult {
+        record("OH_TextConfig_SetAbilityName")?;
+        assert!(length <= 128, "ability name too long");
+        get::<FakeTextConfig, _>(config)?.ability_name = read_utf16_with_nul(abilityName, length);
+        Ok(())
+    }
+}
+
+pub(crate) mod text_editor_proxy {
+    use super::{alloc, free, get, record};
+    pub(crate) use ohos_ime_sys::text_editor_proxy::InputMethod_TextEditorProxy;
+    use ohos_ime_sys::text_editor_proxy::{
+        OH_TextEditorProxy_DeleteBackwardFunc, OH_TextEditorProxy_DeleteForwardFunc,
+        OH_TextEditorProxy_FinishTextPreviewFunc, OH_TextEditorProxy_GetLeftTextOfCursorFunc,
+        OH_TextEditorProxy_GetRightTextOfCursorFunc, OH_TextEditorProxy_GetTextConfigFunc,
+        OH_TextEditorProxy_GetTextIndexAtCursorFunc, OH_TextEditorProxy_HandleExtendActionFunc,
+        OH_TextEditorProxy_HandleSetSelectionFunc, OH_TextEditorProxy_InsertTextFunc,
+        OH_TextEditorProxy_MoveCursorFunc, OH_TextEditorProxy_ReceivePrivateCommandFunc,
+        OH_TextEditorProxy_SendEnterKeyFunc, OH_TextEditorProxy_SendKeyboardStatusFunc,
+        OH_TextEditorProxy_SetPreviewTextFunc,
+    };
+    use ohos_ime_sys::types::InputMethodResult;
+
+    /// The callbacks registered on a fake text editor proxy.
+    #[derive(Copy, Clone, Debug, Default)]
+    pub(crate) struct FakeTextEditorProxy {
+        pub(crate) get_text_config: OH_TextEditorProxy_GetTextConfigFunc,
+        pub(crate) insert_text: OH_TextEditorProxy_InsertTextFunc,
+        pub(crate) delete_forward: OH_TextEditorProxy_DeleteForwardFunc,
+        pub(crate) delete_backward: OH_TextEditorProxy_DeleteBackwardFunc,
+        pub(crate) send_keyboard_status: OH_TextEditorProxy_SendKeyboardStatusFunc,
+        pub(crate) send_enter_key: OH_TextEditorProxy_SendEnterKeyFunc,
+        pub(crate) move_cursor: OH_TextEditorProxy_MoveCursorFunc,
+        pub(crate) handle_set_selection: OH_TextEditorProxy_HandleSetSelectionFunc,
+        pub(crate) handle_extend_action: OH_TextEditorProxy_HandleExtendActionFunc,
+        pub(crate) get_left_text_of_cursor: OH_TextEditorProxy_GetLeftTextOfCursorFunc,
+        pub(crate) get_right_text_of_cursor: OH_TextEditorProxy_GetRightTextOfCursorFunc,
+        pub(crate) get_text_index_at_cursor: OH_TextEditorProxy_GetTextIndexAtCursorFunc,
+        pub(crate) receive_private_command: OH_TextEditorProxy_ReceivePrivateCommandFunc,
+        pub(crate) set_preview_text: OH_TextEditorProxy_SetPreviewTextFunc,
+        pub(crate) finish_text_preview: OH_TextEditorProxy_FinishTextPreviewFunc,
+    }
+
+    /// Return the callbacks registered on a fake text editor proxy.
+    ///
+    /// # Safety
+    ///
+    /// `proxy` must be a live proxy returned by the fake `OH_TextEditorProxy_Create`.
+    pub(crate) unsafe fn fake_editor(
+        proxy: *mut InputMethod_TextEditorProxy,
+    ) -> FakeTextEditorProxy {
+        *get::<FakeTextEditorProxy, _>(proxy).expect("null TextEditorProxy")
+    }
+
+    pub(crate) unsafe fn OH_TextEditorProxy_Create() -> *mut InputMethod_TextEditorProxy {
+        if record("OH_TextEditorProxy_Create").is_err() {
+            return core::ptr::null_mut();
+        }
+        alloc(FakeTextEditorProxy::default())
+    }
+
+    pub(crate) unsafe fn OH_TextEditorProxy_Destroy(proxy: *mut InputMethod_TextEditorProxy) {
+        let _ = record("OH_TextEditorProxy_Destroy");
+        free::<FakeTextEditorProxy, _>(proxy);
+    }
+
+    macro_rules! callback_setters {
+        ($($setter:ident($field:ident: $func:ty);)*) => {
+            $(
+                pub(crate) unsafe fn $setter(
+                    proxy: *mut InputMethod_TextEditorProxy,
+                    func: $func,
+                ) -> InputMethodResult {
+                    record(stringify!($setter))?;
+                    get::<FakeTextEditorProxy, _>(proxy)?.$field = func;
+                    Ok(())
+                }
+            )*
+        };
+    }
+
+    callback_setters! {
+        OH_TextEditorProxy_SetGetTextConfigFunc(get_text_config: OH_TextEditorProxy_GetTextConfigFunc);
+        OH_TextEditorProxy_SetInsertTextFunc(insert_text: OH_TextEditorProxy_InsertTextFunc);
+        OH_TextEditorProxy_SetDeleteForwardFunc(delete_forward: OH_TextEditorProxy_DeleteForwardFunc);
+        OH_TextEditorProxy_SetDeleteBackwardFunc(delete_backward: OH_TextEditorProxy_DeleteBackwardFunc);
+        OH_TextEditorProxy_SetSendKeyboardStatusFunc(send_keyboard_status: OH_TextEditorProxy_SendKeyboardStatusFunc);
+        OH_TextEditorProxy_SetSendEnterKeyFunc(send_enter_key: OH_TextEditorProxy_SendEnterKeyFunc);
+        OH_TextEditorProxy_SetMoveCursorFunc(move_cursor: OH_TextEditorProxy_MoveCursorFunc);
+        OH_TextEditorProxy_SetHandleSetSelectionFunc(handle_set_selection: OH_TextEditorProxy_HandleSetSelectionFunc);
+        OH_TextEditorProxy_SetHandleExtendActionFunc(handle_extend_action: OH_TextEditorProxy_HandleExtendActionFunc);
+        OH_TextEditorProxy_SetGetLeftTextOfCursorFunc(get_left_text_of_cursor: OH_TextEditorProxy_GetLeftTextOfCursorFunc);
+        OH_TextEditorProxy_SetGetRightTextOfCursorFunc(get_right_text_of_cursor: OH_TextEditorProxy_GetRightTextOfCursorFunc);
+        OH_TextEditorProxy_SetGetTextIndexAtCursorFunc(get_text_index_at_cursor: OH_TextEditorProxy_GetTextIndexAtCursorFunc);
+        OH_TextEditorProxy_SetReceivePrivateCommandFunc(receive_private_command: OH_TextEditorProxy_ReceivePrivateCommandFunc);
+        OH_TextEditorProxy_SetSetPreviewTextFunc(set_preview_text: OH_TextEditorProxy_SetPreviewTextFunc);
+        OH_TextEditorProxy_SetFinishTextPreviewFunc(finish_text_preview: OH_TextEditorProxy_FinishTextPreviewFunc);
+    }
+}
@@ -10,24 +10,65 @@
 //!
 //! ## Usage
 //!
-//! 1. Implement the Ime trait
+//! 1. Implement the Ime trait, or use the provided [`TextBufferIme`]
 //! 2. call `ImeProxy::new()`
+//!
+//! ## Panics
+//!
+//! Panics in [`Ime`] methods are caught, since they would otherwise abort the process when
+//! unwinding into the C callbacks. Use [`set_panic_hook()`] to be notified and to decide whether
+//! the affected text editor should be poisoned.
+//!
+//! ## API levels
+//!
+//! The crate targets OpenHarmony API level 12 by default. The `api-13` to `api-26` cargo features
+//! raise the minimum API level and enable the wrappers for newer APIs, e.g. `api-20` enables
+//! `TextConfigBuilder::placeholder()`. Each feature enables the lower levels and the same
+//! feature of `ohos-ime-sys`. The selected level is available as [`API_LEVEL`], and dependent
+//! crates can forward the features to `cfg` on them.
+//!
+//! ## Testing
+//!
+//! With the `testing` feature, the inputmethod API is replaced by an in-memory fake and the
+//! `testing` module allows simulating the system input method, e.g. in unit tests of an editor.
+//! The feature is test-only: it must only be enabled via `dev-dependencies`, and enabling it when
+//! building for OpenHarmony is a compile error, since the input method would silently not work.
+mod dispatcher;
+mod error;
+mod ffi;
+mod panic;
+mod private_command;
+#[cfg(test)]
+mod test_utils;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod text_buffer;
 mod text_config;
 mod text_editor;
+mod text_index;
 
-pub use crate::text_config::{TextConfig, TextConfigBuilder, TextSelection};
-use crate::text_editor::DISPATCHER;
-use log::{error, warn};
-use ohos_ime_sys::attach_options::{
+#[cfg(all(feature = "testing", target_env = "ohos"))]
+compile_error!(
+    "The `testing` feature of `ohos-ime` replaces the inputmethod API with a fake and must not be \
+     enabled for OpenHarmony targets. Only enable it in `dev-dependencies` for host tests."
+);
+
+use crate::dispatcher::{RegisterError, Registration, DISPATCHER};
+pub use crate::error::Error;
+use crate::error::ErrorCode;
+use crate::ffi::attach_options::{
     InputMethod_AttachOptions, OH_AttachOptions_Create, OH_AttachOptions_Destroy,
     OH_AttachOptions_IsShowKeyboard,
 };
-use ohos_ime_sys::controller::{OH_InputMethodController_Attach, OH_InputMethodController_Detach};
-use ohos_ime_sys::inputmethod_proxy::{
+use crate::ffi::controller::{OH_InputMethodController_Attach, OH_InputMethodController_Detach};
+use crate::ffi::cursor_info::{OH_CursorInfo_Create, OH_CursorInfo_Destroy};
+use crate::ffi::inputmethod_proxy::{
     InputMethod_InputMethodProxy, OH_InputMethodProxy_HideKeyboard,
+    OH_InputMethodProxy_NotifyConfigurationChange, OH_InputMethodProxy_NotifyCursorUpdate,
+    OH_InputMethodProxy_NotifySelectionChange, OH_InputMethodProxy_SendPrivateCommand,
     OH_InputMethodProxy_ShowKeyboard,
 };
-use ohos_ime_sys::text_editor_proxy::{
+use crate::ffi::text_editor_proxy::{
     InputMethod_TextEditorProxy, OH_TextEditorProxy_Create, OH_TextEditorProxy_Destroy,
     OH_TextEditorProxy_SetDeleteBackwardFunc, OH_TextEditorProxy_SetDeleteForwardFunc,
     OH_TextEditorProxy_SetFinishTextPreviewFunc, OH_TextEditorProxy_SetGetLeftTextOfCursorFunc,
@@ -38,38 +79,169 @@ use ohos_ime_sys::text_editor_proxy::{
     OH_TextEditorProxy_SetSendEnterKeyFunc, OH_TextEditorProxy_SetSendKeyboardStatusFunc,
     OH_TextEditorProxy_SetSetPreviewTextFunc,
 };
+pub use crate::panic::{set_panic_hook, CallbackPanic, PanicAction};
+pub use crate::private_command::{PrivateCommand, PrivateCommandError, PrivateCommandValue};
+pub use crate::text_buffer::{TextBufferIme, TextBufferState};
+pub use crate::text_config::{
+    EnterKeyType, InvalidSelection, TextConfig, TextConfigBuilder, TextInputType, TextSelection,
+};
+pub use crate::text_editor::ApplyTextConfigError;
+pub use crate::text_index::{delete_backward_range, delete_forward_range, IndexError};
+use log::{error, warn};
 use ohos_ime_sys::types::{
-    InputMethodErrorCode, InputMethodResult, InputMethod_EnterKeyType, InputMethod_KeyboardStatus,
+    InputMethodErrorCode, InputMethodResult, InputMethod_Direction, InputMethod_ExtendAction,
+    InputMethod_KeyboardStatus,
 };
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::ptr::NonNull;
 
+/// Select the highest API level whose feature is enabled, or the baseline.
+macro_rules! api_level {
+    ($baseline:literal, $($level:literal => $feature:literal,)+) => {{
+        let level = $baseline;
+        $(let level = if cfg!(feature = $feature) { $level } else { level };)+
+        level
+    }};
+}
+
+/// The minimum OpenHarmony API level targeted by this build, as selected via the `api-*` features.
+pub const API_LEVEL: u32 = api_level!(
+    12,
+    13 => "api-13",
+    14 => "api-14",
+    15 => "api-15",
+    16 => "api-16",
+    17 => "api-17",
+    18 => "api-18",
+    19 => "api-19",
+    20 => "api-20",
+    21 => "api-21",
+    22 => "api-22",
+    23 => "api-23",
+    24 => "api-24",
+    25 => "api-25",
+    26 => "api-26",
+);
+
 // Todo: Well, honestly we really need to clarify the required sematics on the IME.
 /// User implementation of required Inputmethod functionality
+///
+/// ## Reentrancy
+///
+/// The methods are called without holding any locks of this crate. They may create, attach and
+/// drop [`RawTextEditorProxy`]s and [`ImeProxy`]s, including the one the method is called for,
+/// and may show or hide the keyboard. If the text editor is dropped during a method call, the
+/// `Ime` is dropped after the method returns.
 pub trait Ime: Send + Sync {
     /// Insert `text` at the current cursor position.
     fn insert_text(&self, text: String);
-    /// Delete the next `len` `char`s(?) starting at the current cursor position
+    /// Delete the next `len` utf-16 code units starting at the current cursor position
+    ///
+    /// Use [`delete_forward_range()`] to find the corresponding byte range of the text.
     fn delete_forward(&self, len: usize);
 
-    /// Delete the previous `len` `char`s(?) before the current cursor position
+    /// Delete the previous `len` utf-16 code units before the current cursor position
+    ///
+    /// Use [`delete_backward_range()`] to find the corresponding byte range of the text.
     fn delete_backward(&self, len: usize);
 
     /// Return the text configuration associated with the current IME
-    fn get_text_config(&self) -> &TextConfig;
+    ///
+    /// This is called whenever the input method requests the configuration, e.g. when attaching,
+    /// so the configuration may be computed from the current state of the text field. Use
+    /// [`ImeProxy::notify_config_change()`] to update the input type and enter key type of an
+    /// attached text field.
+    fn get_text_config(&self) -> TextConfig;
 
     /// Process the enter key variant pressed by the user.
     ///
-    /// Depending on the configuration (applied by the implementation of [`get_text_config()`])
-    /// the enterkey label displayed to the user varies.
+    /// Depending on the configuration (applied by the implementation of
+    /// [`get_text_config()`](Ime::get_text_config)) the enterkey label displayed to the user varies.
     /// This function will be called when the enter key is pressed and the associated label
     /// is passed, so that the application can handle it accordingly.
-    fn send_enter_key(&self, enter_key: InputMethod_EnterKeyType);
+    fn send_enter_key(&self, enter_key: EnterKeyType);
 
     /// Called when the status of IME virtual keyboard changes.
     fn keyboard_status_changed(&self, status: KeyboardStatus) {
         log::debug!("Keyboard status changed to {:?}", status);
     }
+
+    /// Move the cursor in the given `direction`.
+    ///
+    /// Called when the user presses one of the arrow keys on the virtual keyboard.
+    fn move_cursor(&self, direction: CursorDirection) {
+        log::debug!("Move cursor request ignored: {:?}", direction);
+    }
+
+    /// Select the text in `range`.
+    ///
+    /// Called when the user adjusts the selection via the input method, e.g. by dragging the
+    /// selection handles or via a "select word" gesture. An empty range places the cursor at
+    /// `range.start()`.
+    fn set_selection(&self, range: TextSelection) {
+        log::debug!("Set selection request ignored: {:?}", range);
+    }
+
+    /// Perform an extended editing action, such as copying the selected text.
+    ///
+    /// Called when the user triggers one of the clipboard actions on the virtual keyboard.
+    fn handle_extend_action(&self, action: ExtendAction) {
+        log::debug!("Extend action ignored: {:?}", action);
+    }
+
+    /// Return the text directly before the cursor.
+    ///
+    /// The input method uses the surrounding text for predictions and autocorrection.
+    /// `n` is the requested length in utf-16 code units. Returning more text than requested is
+    /// allowed, the excess is truncated by this crate, keeping the text closest to the cursor.
+    fn text_before_cursor(&self, n: usize) -> String {
+        log::debug!("text_before_cursor({n}) not implemented by Ime");
+        String::new()
+    }
+
+    /// Return the text directly after the cursor.
+    ///
+    /// See [`Ime::text_before_cursor()`] for details.
+    fn text_after_cursor(&self, n: usize) -> String {
+        log::debug!("text_after_cursor({n}) not implemented by Ime");
+        String::new()
+    }
+
+    /// Return the position of the cursor in utf-16 code units from the start of the text.
+    ///
+    /// Indices larger than `i32::MAX` are clamped.
+    fn cursor_index(&self) -> usize {
+        log::debug!("cursor_index() not implemented by Ime");
+        0
+    }
+
+    /// Show `text` as preview (composing) text.
+    ///
+    /// Only called if preview text support was enabled via
+    /// [`TextConfigBuilder::preview_text_support()`].
+    /// The preview text should replace the text in `range`, or the current preview text
+    /// (or be inserted at the cursor if there is none) if `range` is `None`.
+    /// Preview text is usually displayed underlined and will either be replaced by a later call
+    /// to this function, or committed when [`Ime::finish_preview_text()`] is called.
+    fn set_preview_text(&self, text: String, range: Option<TextSelection>) {
+        log::debug!("Preview text with {} bytes ignored ({range:?})", text.len());
+    }
+
+    /// Commit the current preview text and leave the preview mode.
+    fn finish_preview_text(&self) {
+        log::debug!("finish_preview_text() not implemented by Ime");
+    }
+
+    /// Process private commands sent by the input method.
+    ///
+    /// See also [`ImeProxy::send_private_command()`].
+    fn receive_private_commands(
+        &self,
+        commands: &[PrivateCommand],
+    ) -> Result<(), PrivateCommandError> {
+        log::debug!("Ignoring {} private commands", commands.len());
+        Ok(())
+    }
     // ...
 }
 
@@ -87,13 +259,6 @@ impl Drop for ImeProxy {
         if let Err(e) = unsafe { OH_InputMethodController_Detach(self.raw.as_ptr()) } {
             error!("IME: Detach failed for InputMethodController {:?}", e);
         }
-        let res = DISPATCHER.unregister(self.editor.raw);
-        #[cfg(debug_assertions)]
-        if let Err(e) = res {
-            error!("IME: ImeProxy destroy failed {:?}", e);
-        }
-        #[cfg(not(debug_assertions))]
-        drop(res)
     }
 }
 
@@ -109,6 +274,18 @@ impl Debug for CreateImeProxyError {
     }
 }
 
+impl Display for CreateImeProxyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "attaching to the input method failed: {}",
+            ErrorCode(self.error_code)
+        )
+    }
+}
+
+impl std::error::Error for CreateImeProxyError {}
+
 impl ImeProxy {
     // todo: maybe use builder pattern instead.
     pub fn new(
@@ -144,6 +321,113 @@ impl ImeProxy {
     pub fn hide_keyboard(&self) -> InputMethodResult {
         unsafe { OH_InputMethodProxy_HideKeyboard(self.raw.as_ptr()) }
     }
+
+    /// Whether the [`Ime`] panicked and was poisoned by the hook set via [`set_panic_hook()`].
+    ///
+    /// A poisoned text editor ignores all input method requests. Drop it to detach it.
+    pub fn is_poisoned(&self) -> bool {
+        self.editor.is_poisoned()
+    }
+
+    /// Notify the input method about the position of the cursor.
+    ///
+    /// This allows floating keyboards and candidate windows to be positioned next to the cursor.
+    /// Fails with [`InputMethodErrorCode::NULL_POINTER`] if the cursor info could not be
+    /// allocated.
+    pub fn notify_cursor_update(&self, cursor_info: CursorInfo) -> InputMethodResult {
+        // SAFETY: No particular safety or other requirements.
+        // Only documented failure reason is insufficient Memory
+        let raw_cursor_info = unsafe {
+            OH_CursorInfo_Create(
+                cursor_info.left,
+                cursor_info.top,
+                cursor_info.width,
+                cursor_info.height,
+            )
+        };
+        let raw_cursor_info =
+            NonNull::new(raw_cursor_info).ok_or(InputMethodErrorCode::NULL_POINTER)?;
+        // SAFETY: Both pointers are valid, and the cursor info is only read during the call.
+        let res = unsafe {
+            OH_InputMethodProxy_NotifyCursorUpdate(self.raw.as_ptr(), raw_cursor_info.as_ptr())
+        };
+        // SAFETY: We created the cursor info above and it has no other references.
+        unsafe { OH_CursorInfo_Destroy(raw_cursor_info.as_ptr()) };
+        res
+    }
+
+    /// Notify the input method that the text or selection was changed by the application.
+    ///
+    /// This should be called when the user changes the selection in the editor (e.g. by tapping
+    /// or dragging), so that the input method stays in sync with the editor.
+    /// `selection` is given in utf-16 code units relative to the start of `text`.
+    pub fn notify_selection_change(
+        &self,
+        text: &str,
+        selection: TextSelection,
+    ) -> InputMethodResult {
+        let mut utf16: Vec<u16> = text.encode_utf16().collect();
+        // SAFETY: `utf16` is valid for `utf16.len()` elements, and only read during the call.
+        unsafe {
+            OH_InputMethodProxy_NotifySelectionChange(
+                self.raw.as_ptr(),
+                utf16.as_mut_ptr(),
+                utf16.len(),
+                selection.start,
+                selection.end,
+            )
+        }
+    }
+
+    /// Notify the input method that the text configuration of the editor changed.
+    ///
+    /// This allows e.g. switching a field to a password input without detaching and re-attaching.
+    /// Only the input type and enter key type are forwarded, other fields of `config` are
+    /// ignored by the inputmethod API.
+    pub fn notify_config_change(&self, config: &TextConfig) -> InputMethodResult {
+        // SAFETY: self.raw is valid.
+        unsafe {
+            OH_InputMethodProxy_NotifyConfigurationChange(
+                self.raw.as_ptr(),
+                config.raw_enterkey_type(),
+                config.raw_input_type(),
+            )
+        }
+    }
+
+    /// Send private commands to the input method.
+    ///
+    /// This is intended for communication with a known input method, e.g. a companion keyboard
+    /// extension of the application. The input method is not required to handle the commands.
+    pub fn send_private_command(
+        &self,
+        commands: &[PrivateCommand],
+    ) -> Result<(), PrivateCommandError> {
+        let raw_commands = commands
+            .iter()
+            .map(PrivateCommand::to_raw)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut raw_ptrs: Vec<_> = raw_commands.iter().map(|c| c.as_ptr()).collect();
+        // SAFETY: `raw_ptrs` contains `raw_ptrs.len()` valid commands, which stay alive until
+        // `raw_commands` is dropped after the call.
+        unsafe {
+            OH_InputMethodProxy_SendPrivateCommand(
+                self.raw.as_ptr(),
+                raw_ptrs.as_mut_ptr(),
+                raw_ptrs.len(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Position and size of the cursor, in screen coordinates (px).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CursorInfo {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
 }
 
 pub struct AttachOptions {
@@ -206,14 +490,30 @@ impl Drop for AttachOptions {
 // the Rust impl based on the TextEditorProxy pointer.
 pub struct RawTextEditorProxy {
     raw: NonNull<InputMethod_TextEditorProxy>,
+    /// Owns the [`Ime`], which the callbacks look up via `raw`.
+    registration: Registration,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum CreateTextEditorProxyErrorKind {
     /// Indicates Out of Memory situation
     CreateProxyFailed,
     /// Registering the C callbacks failed.
     RegisterCallbacksFailed(InputMethodErrorCode),
+    /// The text editor proxy returned by the inputmethod API is already in use.
+    AlreadyRegistered,
+    /// Too many text editors exist at the same time.
+    TooManyTextEditors,
+}
+
+impl From<RegisterError> for CreateTextEditorProxyErrorKind {
+    fn from(error: RegisterError) -> Self {
+        match error {
+            RegisterError::AlreadyRegistered => CreateTextEditorProxyErrorKind::AlreadyRegistered,
+            RegisterError::TooManyTextEditors => CreateTextEditorProxyErrorKind::TooManyTextEditors,
+        }
+    }
 }
 pub struct CreateTextEditorProxyError {
     /// Returns the ime passed to [`RawTextEditorProxy::new`].
@@ -221,12 +521,43 @@ pub struct CreateTextEditorProxyError {
     pub reason: CreateTextEditorProxyErrorKind,
 }
 
+impl Display for CreateTextEditorProxyErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CreateTextEditorProxyErrorKind::CreateProxyFailed => {
+                f.write_str("creating the text editor proxy failed")
+            }
+            CreateTextEditorProxyErrorKind::RegisterCallbacksFailed(code) => write!(
+                f,
+                "registering the text editor callbacks failed: {}",
+                ErrorCode(*code)
+            ),
+            CreateTextEditorProxyErrorKind::AlreadyRegistered => {
+                f.write_str("the text editor proxy is already registered")
+            }
+            CreateTextEditorProxyErrorKind::TooManyTextEditors => {
+                f.write_str("too many text editors")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CreateTextEditorProxyErrorKind {}
+
 impl Debug for CreateTextEditorProxyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("{:?}", self.reason))
     }
 }
 
+impl Display for CreateTextEditorProxyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.reason, f)
+    }
+}
+
+impl std::error::Error for CreateTextEditorProxyError {}
+
 impl RawTextEditorProxy {
     pub fn new(ime: Box<dyn Ime>) -> Result<Self, CreateTextEditorProxyError> {
         let raw_proxy = unsafe { OH_TextEditorProxy_Create() };
@@ -237,61 +568,69 @@ impl RawTextEditorProxy {
             });
             return err;
         };
-        let mut proxy = Self { raw: raw_proxy };
-        DISPATCHER.register(proxy.raw, ime);
-        proxy.register_dispatcher_callbacks().map_err(|e| {
-            // unregistering here should never fail
-            let ime = DISPATCHER.unregister(proxy.raw).expect("Unregister failed");
-            CreateTextEditorProxyError {
+        // Register the Ime last, so that we still own it if setting the callbacks fails.
+        // The callbacks can't be called before the editor is attached.
+        if let Err(e) = Self::register_dispatcher_callbacks(raw_proxy) {
+            // SAFETY: We created the proxy above and nothing else references it.
+            unsafe { OH_TextEditorProxy_Destroy(raw_proxy.as_ptr()) };
+            return Err(CreateTextEditorProxyError {
                 ime,
                 reason: CreateTextEditorProxyErrorKind::RegisterCallbacksFailed(e),
+            });
+        }
+        match DISPATCHER.register(raw_proxy, ime) {
+            Ok(registration) => Ok(Self {
+                raw: raw_proxy,
+                registration,
+            }),
+            Err((error, ime)) => {
+                // SAFETY: We created the proxy above and nothing else references it.
+                unsafe { OH_TextEditorProxy_Destroy(raw_proxy.as_ptr()) };
+                Err(CreateTextEditorProxyError {
+                    ime,
+                    reason: error.into(),
+                })
             }
-        })?;
-        Ok(proxy)
+        }
     }
 
-    fn register_dispatcher_callbacks(&mut self) -> InputMethodResult {
+    /// Whether the [`Ime`] panicked and was poisoned by the hook set via [`set_panic_hook()`].
+    pub fn is_poisoned(&self) -> bool {
+        DISPATCHER.is_poisoned(&self.registration)
+    }
+
+    fn register_dispatcher_callbacks(
+        raw: NonNull<InputMethod_TextEditorProxy>,
+    ) -> InputMethodResult {
         use text_editor::*;
         unsafe {
-            OH_TextEditorProxy_SetGetTextConfigFunc(self.raw.as_ptr(), Some(get_text_config))?;
-            OH_TextEditorProxy_SetInsertTextFunc(self.raw.as_ptr(), Some(insert_text))?;
-            OH_TextEditorProxy_SetDeleteForwardFunc(self.raw.as_ptr(), Some(delete_forward))?;
-            OH_TextEditorProxy_SetDeleteBackwardFunc(self.raw.as_ptr(), Some(delete_backward))?;
-            OH_TextEditorProxy_SetSendKeyboardStatusFunc(
-                self.raw.as_ptr(),
-                Some(send_keyboard_status),
-            )?;
-            OH_TextEditorProxy_SetSendEnterKeyFunc(self.raw.as_ptr(), Some(send_enter_key))?;
-            OH_TextEditorProxy_SetMoveCursorFunc(self.raw.as_ptr(), Some(move_cursor))?;
-            OH_TextEditorProxy_SetHandleSetSelectionFunc(
-                self.raw.as_ptr(),
-                Some(handle_set_selection),
-            )?;
-            OH_TextEditorProxy_SetHandleExtendActionFunc(
-                self.raw.as_ptr(),
-                Some(handle_extend_action),
-            )?;
+            OH_TextEditorProxy_SetGetTextConfigFunc(raw.as_ptr(), Some(get_text_config))?;
+            OH_TextEditorProxy_SetInsertTextFunc(raw.as_ptr(), Some(insert_text))?;
+            OH_TextEditorProxy_SetDeleteForwardFunc(raw.as_ptr(), Some(delete_forward))?;
+            OH_TextEditorProxy_SetDeleteBackwardFunc(raw.as_ptr(), Some(delete_backward))?;
+            OH_TextEditorProxy_SetSendKeyboardStatusFunc(raw.as_ptr(), Some(send_keyboard_status))?;
+            OH_TextEditorProxy_SetSendEnterKeyFunc(raw.as_ptr(), Some(send_enter_key))?;
+            OH_TextEditorProxy_SetMoveCursorFunc(raw.as_ptr(), Some(move_cursor))?;
+            OH_TextEditorProxy_SetHandleSetSelectionFunc(raw.as_ptr(), Some(handle_set_selection))?;
+            OH_TextEditorProxy_SetHandleExtendActionFunc(raw.as_ptr(), Some(handle_extend_action))?;
             OH_TextEditorProxy_SetGetLeftTextOfCursorFunc(
-                self.raw.as_ptr(),
+                raw.as_ptr(),
                 Some(get_left_text_of_cursor),
             )?;
             OH_TextEditorProxy_SetGetRightTextOfCursorFunc(
-                self.raw.as_ptr(),
+                raw.as_ptr(),
                 Some(get_right_text_of_cursor),
             )?;
             OH_TextEditorProxy_SetGetTextIndexAtCursorFunc(
-                self.raw.as_ptr(),
+                raw.as_ptr(),
                 Some(get_text_index_at_cursor),
             )?;
             OH_TextEditorProxy_SetReceivePrivateCommandFunc(
-                self.raw.as_ptr(),
+                raw.as_ptr(),
                 Some(receive_private_command),
             )?;
-            OH_TextEditorProxy_SetSetPreviewTextFunc(self.raw.as_ptr(), Some(set_preview_text))?;
-            OH_TextEditorProxy_SetFinishTextPreviewFunc(
-                self.raw.as_ptr(),
-                Some(finish_text_preview),
-            )?;
+            OH_TextEditorProxy_SetSetPreviewTextFunc(raw.as_ptr(), Some(set_preview_text))?;
+            OH_TextEditorProxy_SetFinishTextPreviewFunc(raw.as_ptr(), Some(finish_text_preview))?;
             Ok(())
         }
     }
@@ -299,13 +638,15 @@ impl RawTextEditorProxy {
 
 impl Drop for RawTextEditorProxy {
     fn drop(&mut self) {
+        // Drop the Ime, before the proxy address can be reused.
+        DISPATCHER.unregister(&self.registration);
         unsafe {
             OH_TextEditorProxy_Destroy(self.raw.as_ptr());
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum KeyboardStatus {
     None,
     Hidden,
@@ -332,3 +673,404 @@ impl From<InputMethod_KeyboardStatus> for KeyboardStatus {
         }
     }
 }
+
+impl From<KeyboardStatus> for InputMethod_KeyboardStatus {
+    fn from(status: KeyboardStatus) -> Self {
+        match status {
+            KeyboardStatus::None => InputMethod_KeyboardStatus::IME_KEYBOARD_STATUS_NONE,
+            KeyboardStatus::Hidden => InputMethod_KeyboardStatus::IME_KEYBOARD_STATUS_HIDE,
+            KeyboardStatus::Shown => InputMethod_KeyboardStatus::IME_KEYBOARD_STATUS_SHOW,
+            KeyboardStatus::Unknown(raw) => InputMethod_KeyboardStatus(raw),
+        }
+    }
+}
+
+/// Direction in which the cursor should be moved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CursorDirection {
+    None,
+    Up,
+    Down,
+    Left,
+    Right,
+    Unknown(u32),
+}
+
+impl From<InputMethod_Direction> for CursorDirection {
+    fn from(direction: InputMethod_Direction) -> Self {
+        match direction {
+            InputMethod_Direction::IME_DIRECTION_NONE => CursorDirection::None,
+            InputMethod_Direction::IME_DIRECTION_UP => CursorDirection::Up,
+            InputMethod_Direction::IME_DIRECTION_DOWN => CursorDirection::Down,
+            InputMethod_Direction::IME_DIRECTION_LEFT => CursorDirection::Left,
+            InputMethod_Direction::IME_DIRECTION_RIGHT => CursorDirection::Right,
+            direction => {
+                warn!("Unknown cursor direction enum variant: {}", direction.0);
+                CursorDirection::Unknown(direction.0)
+            }
+        }
+    }
+}
+
+impl From<CursorDirection> for InputMethod_Direction {
+    fn from(direction: CursorDirection) -> Self {
+        match direction {
+            CursorDirection::None => InputMethod_Direction::IME_DIRECTION_NONE,
+            CursorDirection::Up => InputMethod_Direction::IME_DIRECTION_UP,
+            CursorDirection::Down => InputMethod_Direction::IME_DIRECTION_DOWN,
+            CursorDirection::Left => InputMethod_Direction::IME_DIRECTION_LEFT,
+            CursorDirection::Right => InputMethod_Direction::IME_DIRECTION_RIGHT,
+            CursorDirection::Unknown(raw) => InputMethod_Direction(raw),
+        }
+    }
+}
+
+/// Extended editing action requested by the input method.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExtendAction {
+    /// Select all text.
+    SelectAll,
+    /// Cut the selected text.
+    Cut,
+    /// Copy the selected text.
+    Copy,
+    /// Paste from the pasteboard.
+    Paste,
+    Unknown(u32),
+}
+
+impl From<InputMethod_ExtendAction> for ExtendAction {
+    fn from(action: InputMethod_ExtendAction) -> Self {
+        match action {
+            InputMethod_ExtendAction::IME_EXTEND_ACTION_SELECT_ALL => ExtendAction::SelectAll,
+            InputMethod_ExtendAction::IME_EXTEND_ACTION_CUT => ExtendAction::Cut,
+            InputMethod_ExtendAction::IME_EXTEND_ACTION_COPY => ExtendAction::Copy,
+            InputMethod_ExtendAction::IME_EXTEND_ACTION_PASTE => ExtendAction::Paste,
+            action => {
+                warn!("Unknown extend action enum variant: {}", action.0);
+                ExtendAction::Unknown(action.0)
+            }
+        }
+    }
+}
+
+impl From<ExtendAction> for InputMethod_ExtendAction {
+    fn from(action: ExtendAction) -> Self {
+        match action {
+            ExtendAction::SelectAll => InputMethod_ExtendAction::IME_EXTEND_ACTION_SELECT_ALL,
+            ExtendAction::Cut => InputMethod_ExtendAction::IME_EXTEND_ACTION_CUT,
+            ExtendAction::Copy => InputMethod_ExtendAction::IME_EXTEND_ACTION_COPY,
+            ExtendAction::Paste => InputMethod_ExtendAction::IME_EXTEND_ACTION_PASTE,
+            ExtendAction::Unknown(raw) => InputMethod_ExtendAction(raw),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::fake::{inject_error, take_calls};
+    use crate::ffi::inputmethod_proxy::fake_proxy;
+    use crate::ffi::private_command::{FakeCommandValue, FakePrivateCommand};
+    use crate::ffi::text_editor_proxy::{fake_editor, FakeTextEditorProxy};
+    use crate::test_utils::{Attached, RecordingIme};
+    use ohos_ime_sys::types::{InputMethod_EnterKeyType, InputMethod_TextInputType};
+    use std::sync::Arc;
+
+    #[test]
+    fn drop_detaches_before_destroying_the_editor() {
+        let attached = Attached::new(RecordingIme::default());
+        take_calls();
+        drop(attached);
+        let calls = take_calls();
+        let detach = calls
+            .iter()
+            .position(|&c| c == "OH_InputMethodController_Detach");
+        let destroy = calls
+            .iter()
+            .position(|&c| c == "OH_TextEditorProxy_Destroy");
+        assert!(detach.unwrap() < destroy.unwrap(), "{calls:?}");
+    }
+
+    #[test]
+    fn attach_failure_returns_editor_and_options() {
+        let editor = RawTextEditorProxy::new(Box::new(RecordingIme::default())).unwrap();
+        inject_error(
+            "OH_InputMethodController_Attach",
+            InputMethodErrorCode::IMCLIENT,
+        );
+        let Err(err) = ImeProxy::new(editor, AttachOptions::new(false)) else {
+            panic!("Attach should fail");
+        };
+        assert_eq!(err.error_code, InputMethodErrorCode::IMCLIENT);
+        // The editor is still usable.
+        ImeProxy::new(err.editor, err.options).unwrap();
+    }
+
+    #[test]
+    fn register_callbacks_failure_returns_ime() {
+        inject_error(
+            "OH_TextEditorProxy_SetMoveCursorFunc",
+            InputMethodErrorCode::NULL_POINTER,
+        );
+        let Err(err) = RawTextEditorProxy::new(Box::new(RecordingIme::default())) else {
+            panic!("Registering callbacks should fail");
+        };
+        assert!(matches!(
+            err.reason,
+            CreateTextEditorProxyErrorKind::RegisterCallbacksFailed(
+                InputMethodErrorCode::NULL_POINTER
+            )
+        ));
+        RawTextEditorProxy::new(err.ime).unwrap();
+    }
+
+    #[test]
+    fn keyboard_visibility() {
+        let attached = Attached::new(RecordingIme::default());
+        let keyboard_shown = || unsafe { fake_proxy(attached.proxy.raw.as_ptr()) }.keyboard_shown;
+        assert!(keyboard_shown());
+        attached.proxy.hide_keyboard().unwrap();
+        assert!(!keyboard_shown());
+        attached.proxy.show_keyboard().unwrap();
+        assert!(keyboard_shown());
+    }
+
+    #[test]
+    fn notifications() {
+        let attached = Attached::new(RecordingIme::default());
+        let proxy = &attached.proxy;
+        proxy
+            .notify_selection_change("a😀", TextSelection::new(1, 3).unwrap())
+            .unwrap();
+        let cursor = CursorInfo {
+            left: 1.0,
+            top: 2.0,
+            width: 3.0,
+            height: 4.0,
+        };
+        proxy.notify_cursor_update(cursor).unwrap();
+        let config = TextConfigBuilder::new()
+            .input_type(TextInputType::VisiblePassword)
+            .enterkey_type(EnterKeyType::Go)
+            .build();
+        proxy.notify_config_change(&config).unwrap();
+
+        let fake = unsafe { fake_proxy(proxy.raw.as_ptr()) };
+        assert_eq!(
+            fake.selection_change,
+            Some(("a😀".encode_utf16().collect(), 1, 3))
+        );
+        let fake_cursor = fake.cursor_info.unwrap();
+        assert_eq!(
+            (
+                fake_cursor.left,
+                fake_cursor.top,
+                fake_cursor.width,
+                fake_cursor.height
+            ),
+            (1.0, 2.0, 3.0, 4.0)
+        );
+        assert_eq!(
+            fake.configuration_change,
+            Some((
+                InputMethod_EnterKeyType::IME_ENTER_KEY_GO,
+                InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_VISIBLE_PASSWORD
+            ))
+        );
+
+        crate::ffi::fake::inject_error("OH_CursorInfo_Create", InputMethodErrorCode::UNDEFINED);
+        assert_eq!(
+            proxy.notify_cursor_update(cursor),
+            Err(InputMethodErrorCode::NULL_POINTER)
+        );
+    }
+
+    #[test]
+    fn send_private_command() {
+        let attached = Attached::new(RecordingIme::default());
+        let commands = [
+            PrivateCommand::new("count", PrivateCommandValue::Int(-3)),
+            PrivateCommand::new("text", PrivateCommandValue::String("ä".to_string())),
+        ];
+        attached.proxy.send_private_command(&commands).unwrap();
+        let fake = unsafe { fake_proxy(attached.proxy.raw.as_ptr()) };
+        assert_eq!(
+            fake.private_commands,
+            vec![
+                FakePrivateCommand {
+                    key: b"count".to_vec(),
+                    value: FakeCommandValue::Int(-3),
+                },
+                FakePrivateCommand {
+                    key: b"text".to_vec(),
+                    value: FakeCommandValue::Str("ä".as_bytes().to_vec()),
+                },
+            ]
+        );
+        // All commands we created are destroyed again.
+        let calls = take_calls();
+        let created = calls.iter().filter(|&&c| c == "OH_PrivateCommand_Create");
+        let destroyed = calls.iter().filter(|&&c| c == "OH_PrivateCommand_Destroy");
+        assert_eq!(created.count(), destroyed.count());
+    }
+
+    struct PanickingIme {
+        config: TextConfig,
+        inserted: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Ime for PanickingIme {
+        fn insert_text(&self, text: String) {
+            if text.starts_with("panic") {
+                panic!("{text}");
+            }
+            self.inserted.lock().unwrap().push(text);
+        }
+        fn delete_forward(&self, _len: usize) {}
+        fn delete_backward(&self, _len: usize) {}
+        fn get_text_config(&self) -> TextConfig {
+            self.config.clone()
+        }
+        fn send_enter_key(&self, _enter_key: EnterKeyType) {}
+        fn cursor_index(&self) -> usize {
+            panic!("cursor_index")
+        }
+    }
+
+    #[test]
+    fn panics_are_caught_and_may_poison_the_editor() {
+        let inserted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let ime = PanickingIme {
+            config: TextConfig::default(),
+            inserted: inserted.clone(),
+        };
+        let editor = RawTextEditorProxy::new(Box::new(ime)).unwrap();
+        let proxy = ImeProxy::new(editor, AttachOptions::new(true)).unwrap();
+        let fake = testing::FakeInputMethod::for_ime_proxy(&proxy);
+
+        // Without a hook, the editor keeps working.
+        fake.insert_text("panic");
+        assert_eq!(fake.cursor_index(), 0);
+        fake.insert_text("a");
+
+        /// Resets the process-global hook, even if an assertion fails.
+        struct ResetPanicHook;
+        impl Drop for ResetPanicHook {
+            fn drop(&mut self) {
+                crate::panic::reset_panic_hook();
+            }
+        }
+        let _reset = ResetPanicHook;
+        let panics = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let panics_clone = panics.clone();
+        let test_thread = std::thread::current().id();
+        set_panic_hook(move |panic| {
+            // Tests run in parallel, ignore the panics of other tests.
+            if std::thread::current().id() != test_thread {
+                return PanicAction::Continue;
+            }
+            let message = panic.message().unwrap_or_default().to_string();
+            panics_clone
+                .lock()
+                .unwrap()
+                .push((panic.callback(), message.clone()));
+            if message == "panic and poison" {
+                PanicAction::Poison
+            } else {
+                PanicAction::Continue
+            }
+        });
+        fake.insert_text("panic again");
+        fake.insert_text("b");
+        assert!(!proxy.is_poisoned());
+        assert_eq!(fake.set_preview_text("p", None), 0);
+        fake.insert_text("panic and poison");
+        assert!(proxy.is_poisoned());
+        fake.insert_text("c");
+        assert_ne!(fake.set_preview_text("p", None), 0);
+
+        assert_eq!(*inserted.lock().unwrap(), vec!["a", "b"]);
+        assert_eq!(
+            *panics.lock().unwrap(),
+            vec![
+                ("insert_text", "panic again".to_string()),
+                ("insert_text", "panic and poison".to_string()),
+            ]
+        );
+    }
+
+    thread_local! {
+        /// The proxy of [`ReentrantIme`]. `ImeProxy` is not `Send`, so it can't live in the Ime.
+        static REENTRANT_PROXY: std::cell::RefCell<Option<ImeProxy>> = const { std::cell::RefCell::new(None) };
+    }
+
+    /// An [`Ime`] which manages its own [`ImeProxy`] from within its methods.
+    struct ReentrantIme(TextConfig);
+
+    impl ReentrantIme {
+        fn attach() {
+            let editor = RawTextEditorProxy::new(Box::new(ReentrantIme(TextConfig::default())));
+            let proxy = ImeProxy::new(editor.unwrap(), AttachOptions::new(false)).unwrap();
+            REENTRANT_PROXY.with_borrow_mut(|slot| *slot = Some(proxy));
+        }
+    }
+
+    impl Ime for ReentrantIme {
+        fn insert_text(&self, text: String) {
+            match text.as_str() {
+                "show" => REENTRANT_PROXY.with_borrow(|proxy| {
+                    proxy.as_ref().unwrap().show_keyboard().unwrap();
+                }),
+                // Replacing the proxy drops the one this method is called for.
+                "reattach" => ReentrantIme::attach(),
+                "detach" => drop(REENTRANT_PROXY.take()),
+                _ => {}
+            }
+        }
+        fn delete_forward(&self, _len: usize) {}
+        fn delete_backward(&self, _len: usize) {}
+        fn get_text_config(&self) -> TextConfig {
+            self.0.clone()
+        }
+        fn send_enter_key(&self, _enter_key: EnterKeyType) {}
+    }
+
+    #[test]
+    fn ime_methods_may_call_back_into_the_proxy() {
+        let callbacks = || {
+            REENTRANT_PROXY.with_borrow(|proxy| {
+                let proxy = proxy.as_ref().unwrap();
+                // SAFETY: The editor is alive as long as `proxy`.
+                let callbacks = unsafe { fake_editor(proxy.editor.raw.as_ptr()) };
+                (proxy.editor.raw.as_ptr(), callbacks)
+            })
+        };
+        let insert_text = |(editor, callbacks): (*mut _, FakeTextEditorProxy), text: &str| {
+            let utf16: Vec<u16> = text.encode_utf16().collect();
+            // SAFETY: `utf16` is valid for `utf16.len()` elements.
+            unsafe { callbacks.insert_text.unwrap()(editor, utf16.as_ptr(), utf16.len()) };
+        };
+
+        ReentrantIme::attach();
+        insert_text(callbacks(), "show");
+        REENTRANT_PROXY.with_borrow(|proxy| {
+            let proxy = proxy.as_ref().unwrap();
+            assert!(unsafe { fake_proxy(proxy.raw.as_ptr()) }.keyboard_shown);
+        });
+        let first = callbacks();
+        insert_text(first, "reattach");
+        let second = callbacks();
+        insert_text(second, "detach");
+        assert!(REENTRANT_PROXY.with_borrow(Option::is_none));
+    }
+
+    #[cfg(feature = "api-20")]
+    #[test]
+    fn api_20_enables_placeholder_support() {
+        // Dependent crates rely on `API_LEVEL` to decide whether the api-20 setters are available.
+        const _: () = assert!(API_LEVEL >= 20);
+        let config = TextConfigBuilder::new().placeholder("Name").build();
+        assert_eq!(config.placeholder.as_deref(), Some("Name"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Handling of panics in [`Ime`](crate::Ime) implementations.
+//!
+//! The [`Ime`](crate::Ime) methods are called from `extern "C"` callbacks, where unwinding would
+//! abort the process. Panics are therefore caught, logged and reported to the hook installed via
+//! [`set_panic_hook()`].
+
+use log::error;
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// What to do with a text editor after its [`Ime`](crate::Ime) panicked.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PanicAction {
+    /// Keep forwarding input method requests to the [`Ime`](crate::Ime).
+    #[default]
+    Continue,
+    /// Ignore all further input method requests for this text editor.
+    ///
+    /// Other text editors are not affected. Use
+    /// [`ImeProxy::is_poisoned()`](crate::ImeProxy::is_poisoned) to check if a text editor was
+    /// poisoned, and drop it to detach it from the input method.
+    Poison,
+}
+
+/// Information about a panic in an [`Ime`](crate::Ime) method.
+pub struct CallbackPanic<'a> {
+    callback: &'static str,
+    payload: &'a (dyn Any + Send),
+}
+
+impl CallbackPanic<'_> {
+    /// The name of the input method callback, which called the panicking method, e.g.
+    /// `insert_text`.
+    pub fn callback(&self) -> &'static str {
+        self.callback
+    }
+
+    /// The panic message, if the panic payload is a string.
+    pub fn message(&self) -> Option<&str> {
+        if let Some(message) = self.payload.downcast_ref::<&'static str>() {
+            Some(message)
+        } else {
+            self.payload.downcast_ref::<String>().map(String::as_str)
+        }
+    }
+
+    /// The panic payload, as passed to [`std::panic::panic_any()`].
+    pub fn payload(&self) -> &(dyn Any + Send) {
+        self.payload
+    }
+}
+
+type PanicHook = Arc<dyn Fn(&CallbackPanic<'_>) -> PanicAction + Send + Sync>;
+
+static PANIC_HOOK: RwLock<Option<PanicHook>> = RwLock::new(None);
+
+/// Set the function called when an [`Ime`](crate::Ime) method panics.
+///
+/// The hook decides via the returned [`PanicAction`] whether the affected text editor is
+/// poisoned. Without a hook, panics are only logged and [`PanicAction::Continue`] is used.
+///
+/// The hook replaces any previously set hook and applies to all text editors.
+pub fn set_panic_hook(hook: impl Fn(&CallbackPanic<'_>) -> PanicAction + Send + Sync + 'static) {
+    *PANIC_HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(hook));
+}
+
+/// Remove the hook set via [`set_panic_hook()`].
+#[cfg(test)]
+pub(crate) fn reset_panic_hook() {
+    *PANIC_HOOK.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Log the panic and ask the hook how to proceed.
+pub(crate) fn handle_panic(callback: &'static str, payload: &(dyn Any + Send)) -> PanicAction {
+    let panic = CallbackPanic { callback, payload };
+    error!(
+        "IME callback `{callback}` panicked: {}",
+        panic.message().unwrap_or("<non-string payload>")
+    );
+    let hook = PANIC_HOOK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let Some(hook) = hook else {
+        return PanicAction::default();
+    };
+    // The hook must not unwind into the `extern "C"` callback either.
+    catch_unwind(AssertUnwindSafe(|| hook(&panic))).unwrap_or_else(|_| {
+        error!("IME panic hook panicked");
+        PanicAction::default()
+    })
+}
@@ -0,0 +1,189 @@
+use crate::error::ErrorCode;
+use crate::ffi::private_command::{
+    InputMethod_PrivateCommand, OH_PrivateCommand_Create, OH_PrivateCommand_Destroy,
+    OH_PrivateCommand_GetBoolValue, OH_PrivateCommand_GetIntValue, OH_PrivateCommand_GetKey,
+    OH_PrivateCommand_GetStrValue, OH_PrivateCommand_GetValueType, OH_PrivateCommand_SetBoolValue,
+    OH_PrivateCommand_SetIntValue, OH_PrivateCommand_SetStrValue,
+};
+use log::error;
+use ohos_ime_sys::types::{InputMethodErrorCode, InputMethod_CommandValueType};
+use std::ffi::c_char;
+use std::fmt::{Display, Formatter};
+use std::ptr::NonNull;
+
+/// Custom data exchanged between the application and the input method.
+///
+/// Private commands can be used to communicate with a known input method, e.g. a companion
+/// keyboard extension of the application.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivateCommand {
+    pub key: String,
+    pub value: PrivateCommandValue,
+}
+
+/// The value of a [`PrivateCommand`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrivateCommandValue {
+    Bool(bool),
+    Int(i32),
+    String(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PrivateCommandError {
+    /// The private command was rejected by the receiver.
+    Rejected,
+    /// The raw private command could not be converted, e.g. because it is not valid utf-8.
+    InvalidCommand,
+    /// Creating the private command failed, presumably due to an Out of Memory situation.
+    CreateFailed,
+    /// The underlying inputmethod API reported an error.
+    InputMethod(InputMethodErrorCode),
+}
+
+impl Display for PrivateCommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivateCommandError::Rejected => f.write_str("the private command was rejected"),
+            PrivateCommandError::InvalidCommand => f.write_str("invalid private command"),
+            PrivateCommandError::CreateFailed => f.write_str("creating a private command failed"),
+            PrivateCommandError::InputMethod(code) => {
+                write!(f, "sending private commands failed: {}", ErrorCode(*code))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrivateCommandError {}
+
+impl From<InputMethodErrorCode> for PrivateCommandError {
+    fn from(code: InputMethodErrorCode) -> Self {
+        PrivateCommandError::InputMethod(code)
+    }
+}
+
+impl PrivateCommand {
+    pub fn new(key: impl Into<String>, value: PrivateCommandValue) -> PrivateCommand {
+        PrivateCommand {
+            key: key.into(),
+            value,
+        }
+    }
+
+    /// Convert a private command received from the input method.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to a valid `InputMethod_PrivateCommand`.
+    pub(crate) unsafe fn from_raw(
+        raw: NonNull<InputMethod_PrivateCommand>,
+    ) -> Result<PrivateCommand, PrivateCommandError> {
+        let raw = raw.as_ptr();
+        let mut key: *const c_char = core::ptr::null();
+        let mut key_len: usize = 0;
+        OH_PrivateCommand_GetKey(raw, &raw mut key, &raw mut key_len)?;
+        let key = string_from_raw_parts(key, key_len)?;
+
+        let mut value_type = InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_NONE;
+        OH_PrivateCommand_GetValueType(raw, &raw mut value_type)?;
+        let value = match value_type {
+            InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_BOOL => {
+                let mut value: u8 = 0;
+                const _: () = assert!(size_of::<u8>() == size_of::<bool>());
+                // We don't want to rely on the C-side writing a valid bool, so we check the raw
+                // `u8` value, like in `AttachOptions::get_visibility()`.
+                OH_PrivateCommand_GetBoolValue(raw, (&raw mut value).cast())?;
+                PrivateCommandValue::Bool(value != 0)
+            }
+            InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_INT32 => {
+                let mut value: i32 = 0;
+                OH_PrivateCommand_GetIntValue(raw, &raw mut value)?;
+                PrivateCommandValue::Int(value)
+            }
+            InputMethod_CommandValueType::IME_COMMAND_VALUE_TYPE_STRING => {
+                let mut value: *const c_char = core::ptr::null();
+                let mut value_len: usize = 0;
+                OH_PrivateCommand_GetStrValue(raw, &raw mut value, &raw mut value_len)?;
+                PrivateCommandValue::String(string_from_raw_parts(value, value_len)?)
+            }
+            value_type => {
+                error!("Unsupported private command value type: {}", value_type.0);
+                return Err(PrivateCommandError::InvalidCommand);
+            }
+        };
+        Ok(PrivateCommand { key, value })
+    }
+
+    /// Create a raw private command, which can be passed to the input method.
+    pub(crate) fn to_raw(&self) -> Result<RawPrivateCommand, PrivateCommandError> {
+        // SAFETY: The key is only read by OH_PrivateCommand_Create and copied.
+        let raw = unsafe {
+            OH_PrivateCommand_Create(
+                self.key.as_ptr().cast::<c_char>().cast_mut(),
+                self.key.len(),
+            )
+        };
+        let raw = RawPrivateCommand {
+            raw: NonNull::new(raw).ok_or(PrivateCommandError::CreateFailed)?,
+        };
+        // SAFETY: raw is valid, and string values are only read and copied.
+        unsafe {
+            match &self.value {
+                PrivateCommandValue::Bool(value) => {
+                    OH_PrivateCommand_SetBoolValue(raw.raw.as_ptr(), *value)?
+                }
+                PrivateCommandValue::Int(value) => {
+                    OH_PrivateCommand_SetIntValue(raw.raw.as_ptr(), *value)?
+                }
+                PrivateCommandValue::String(value) => OH_PrivateCommand_SetStrValue(
+                    raw.raw.as_ptr(),
+                    value.as_ptr().cast::<c_char>().cast_mut(),
+                    value.len(),
+                )?,
+            }
+        }
+        Ok(raw)
+    }
+}
+
+/// Copy a (not necessarily NUL-terminated) utf-8 string owned by the inputmethod API.
+///
+/// # Safety
+///
+/// `ptr` must be valid for `len` bytes, or `len` must be zero.
+unsafe fn string_from_raw_parts(
+    ptr: *const c_char,
+    len: usize,
+) -> Result<String, PrivateCommandError> {
+    if len == 0 {
+        return Ok(String::new());
+    }
+    if ptr.is_null() {
+        error!("Private command string with len {len} is a null pointer");
+        return Err(PrivateCommandError::InvalidCommand);
+    }
+    let bytes = core::slice::from_raw_parts(ptr.cast::<u8>(), len);
+    String::from_utf8(bytes.to_vec()).map_err(|e| {
+        error!("Private command contains invalid utf-8: {e:?}");
+        PrivateCommandError::InvalidCommand
+    })
+}
+
+/// Owned `InputMethod_PrivateCommand`, which is destroyed on drop.
+pub(crate) struct RawPrivateCommand {
+    raw: NonNull<InputMethod_PrivateCommand>,
+}
+
+impl RawPrivateCommand {
+    pub(crate) fn as_ptr(&self) -> *mut InputMethod_PrivateCommand {
+        self.raw.as_ptr()
+    }
+}
+
+impl Drop for RawPrivateCommand {
+    fn drop(&mut self) {
+        // SAFETY: We created the command and never hand out ownership.
+        unsafe { OH_PrivateCommand_Destroy(self.raw.as_ptr()) }
+    }
+}
@@ -0,0 +1,131 @@
+//! Helpers shared by the unit tests.
+
+use crate::ffi::text_editor_proxy::{fake_editor, FakeTextEditorProxy};
+use crate::{
+    AttachOptions, CursorDirection, EnterKeyType, ExtendAction, Ime, ImeProxy, KeyboardStatus,
+    PrivateCommand, PrivateCommandError, RawTextEditorProxy, TextConfig, TextSelection,
+};
+use std::sync::{Arc, Mutex};
+
+/// A call to one of the [`Ime`] methods.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Event {
+    InsertText(String),
+    DeleteForward(usize),
+    DeleteBackward(usize),
+    SendEnterKey(EnterKeyType),
+    KeyboardStatusChanged(KeyboardStatus),
+    MoveCursor(CursorDirection),
+    SetSelection(TextSelection),
+    ExtendAction(ExtendAction),
+    SetPreviewText(String, Option<TextSelection>),
+    FinishPreviewText,
+    PrivateCommands(Vec<PrivateCommand>),
+}
+
+/// An [`Ime`] which records all calls and returns canned responses.
+#[derive(Default)]
+pub(crate) struct RecordingIme {
+    pub(crate) events: Arc<Mutex<Vec<Event>>>,
+    pub(crate) config: TextConfig,
+    pub(crate) text_before_cursor: String,
+    pub(crate) text_after_cursor: String,
+    pub(crate) cursor_index: usize,
+}
+
+impl RecordingIme {
+    fn push(&self, event: Event) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Ime for RecordingIme {
+    fn insert_text(&self, text: String) {
+        self.push(Event::InsertText(text));
+    }
+
+    fn delete_forward(&self, len: usize) {
+        self.push(Event::DeleteForward(len));
+    }
+
+    fn delete_backward(&self, len: usize) {
+        self.push(Event::DeleteBackward(len));
+    }
+
+    fn get_text_config(&self) -> TextConfig {
+        self.config.clone()
+    }
+
+    fn send_enter_key(&self, enter_key: EnterKeyType) {
+        self.push(Event::SendEnterKey(enter_key));
+    }
+
+    fn keyboard_status_changed(&self, status: KeyboardStatus) {
+        self.push(Event::KeyboardStatusChanged(status));
+    }
+
+    fn move_cursor(&self, direction: CursorDirection) {
+        self.push(Event::MoveCursor(direction));
+    }
+
+    fn set_selection(&self, range: TextSelection) {
+        self.push(Event::SetSelection(range));
+    }
+
+    fn handle_extend_action(&self, action: ExtendAction) {
+        self.push(Event::ExtendAction(action));
+    }
+
+    fn text_before_cursor(&self, _n: usize) -> String {
+        self.text_before_cursor.clone()
+    }
+
+    fn text_after_cursor(&self, _n: usize) -> String {
+        self.text_after_cursor.clone()
+    }
+
+    fn cursor_index(&self) -> usize {
+        self.cursor_index
+    }
+
+    fn set_preview_text(&self, text: String, range: Option<TextSelection>) {
+        self.push(Event::SetPreviewText(text, range));
+    }
+
+    fn finish_preview_text(&self) {
+        self.push(Event::FinishPreviewText);
+    }
+
+    fn receive_private_commands(
+        &self,
+        commands: &[PrivateCommand],
+    ) -> Result<(), PrivateCommandError> {
+        self.push(Event::PrivateCommands(commands.to_vec()));
+        Ok(())
+    }
+}
+
+/// An attached [`RecordingIme`].
+pub(crate) struct Attached {
+    pub(crate) proxy: ImeProxy,
+    pub(crate) events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Attached {
+    pub(crate) fn new(ime: RecordingIme) -> Attached {
+        let events = ime.events.clone();
+        let editor = RawTextEditorProxy::new(Box::new(ime)).expect("create editor failed");
+        let proxy = ImeProxy::new(editor, AttachOptions::new(true)).expect("attach failed");
+        Attached { proxy, events }
+    }
+
+    /// The callbacks the input method would call.
+    pub(crate) fn callbacks(&self) -> FakeTextEditorProxy {
+        // SAFETY: The editor is alive as long as `self.proxy`.
+        unsafe { fake_editor(self.proxy.editor.raw.as_ptr()) }
+    }
+
+    pub(crate) fn take_events(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
@@ -0,0 +1,258 @@
+//! Simulated input method for testing applications off-device.
+//!
+//! Enabling the `testing` feature replaces the inputmethod API of OpenHarmony with an in-memory
+//! fake, so that [`RawTextEditorProxy`] and [`ImeProxy`] can be created on any host.
+//! [`FakeInputMethod`] then drives the [`Ime`](crate::Ime) implementation the same way the system
+//! input method would, by calling the C callbacks registered by this crate.
+//!
+//! **Only enable this feature for `dev-dependencies`**, since it disables the real input method
+//! for the whole dependency graph. To prevent this from going unnoticed, enabling the feature when
+//! building for OpenHarmony (`target_env = "ohos"`) is a compile error.
+//!
+//! ```ignore
+//! let editor = RawTextEditorProxy::new(Box::new(MyEditor::default())).unwrap();
+//! let proxy = ImeProxy::new(editor, AttachOptions::new(true)).unwrap();
+//! let ime = FakeInputMethod::for_ime_proxy(&proxy);
+//! ime.insert_text("Hello");
+//! ime.delete_backward(1);
+//! assert_eq!(ime.text_before_cursor(10), "Hell");
+//! ```
+
+use crate::ffi::inputmethod_proxy::fake_proxy;
+use crate::ffi::text_editor_proxy::{fake_editor, FakeTextEditorProxy};
+use crate::{
+    CursorDirection, EnterKeyType, ExtendAction, ImeProxy, KeyboardStatus, RawTextEditorProxy,
+};
+use ohos_ime_sys::text_editor_proxy::{
+    InputMethod_TextEditorProxy, OH_TextEditorProxy_GetLeftTextOfCursorFunc,
+};
+use ohos_ime_sys::types::{
+    InputMethod_Direction, InputMethod_EnterKeyType, InputMethod_ExtendAction,
+    InputMethod_KeyboardStatus,
+};
+use std::marker::PhantomData;
+
+/// Simulates the system input method for a [`RawTextEditorProxy`].
+///
+/// The methods mirror the requests the input method sends to the text editor. They call the
+/// registered callbacks synchronously, so the [`Ime`](crate::Ime) implementation has processed
+/// the request when the method returns.
+pub struct FakeInputMethod<'a> {
+    editor: *mut InputMethod_TextEditorProxy,
+    callbacks: FakeTextEditorProxy,
+    ime_proxy: Option<&'a ImeProxy>,
+    _editor: PhantomData<&'a RawTextEditorProxy>,
+}
+
+impl<'a> FakeInputMethod<'a> {
+    /// Simulate the input method for a text editor, which is not attached.
+    pub fn new(editor: &'a RawTextEditorProxy) -> FakeInputMethod<'a> {
+        let raw = editor.raw.as_ptr();
+        FakeInputMethod {
+            editor: raw,
+            // SAFETY: `editor` is alive for `'a`.
+            callbacks: unsafe { fake_editor(raw) },
+            ime_proxy: None,
+            _editor: PhantomData,
+        }
+    }
+
+    /// Simulate the input method for an attached text editor.
+    pub fn for_ime_proxy(proxy: &'a ImeProxy) -> FakeInputMethod<'a> {
+        FakeInputMethod {
+            ime_proxy: Some(proxy),
+            ..FakeInputMethod::new(&proxy.editor)
+        }
+    }
+
+    /// Whether the keyboard is currently shown.
+    ///
+    /// Returns `None` if the input method was not created via [`FakeInputMethod::for_ime_proxy`].
+    pub fn is_keyboard_shown(&self) -> Option<bool> {
+        self.ime_proxy
+            // SAFETY: The proxy is attached as long as it is alive.
+            .map(|proxy| unsafe { fake_proxy(proxy.raw.as_ptr()) }.keyboard_shown)
+    }
+
+    /// Insert `text` at the cursor, like typing on the virtual keyboard.
+    pub fn insert_text(&self, text: &str) {
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        if let Some(f) = self.callbacks.insert_text {
+            // SAFETY: `utf16` is valid for `utf16.len()` elements.
+            unsafe { f(self.editor, utf16.as_ptr(), utf16.len()) }
+        }
+    }
+
+    /// Delete `len` utf-16 code units after the cursor.
+    pub fn delete_forward(&self, len: i32) {
+        if let Some(f) = self.callbacks.delete_forward {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, len) }
+        }
+    }
+
+    /// Delete `len` utf-16 code units before the cursor, like pressing backspace.
+    pub fn delete_backward(&self, len: i32) {
+        if let Some(f) = self.callbacks.delete_backward {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, len) }
+        }
+    }
+
+    /// Press the enter key, which is labeled according to `enter_key`.
+    pub fn send_enter_key(&self, enter_key: EnterKeyType) {
+        if let Some(f) = self.callbacks.send_enter_key {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, InputMethod_EnterKeyType::from(enter_key)) }
+        }
+    }
+
+    /// Notify the editor about a change of the virtual keyboard status.
+    ///
+    /// Note that this does not change [`FakeInputMethod::is_keyboard_shown`], which tracks
+    /// [`ImeProxy::show_keyboard`] and [`ImeProxy::hide_keyboard`].
+    pub fn send_keyboard_status(&self, status: KeyboardStatus) {
+        if let Some(f) = self.callbacks.send_keyboard_status {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, InputMethod_KeyboardStatus::from(status)) }
+        }
+    }
+
+    /// Press an arrow key on the virtual keyboard.
+    pub fn move_cursor(&self, direction: CursorDirection) {
+        if let Some(f) = self.callbacks.move_cursor {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, InputMethod_Direction::from(direction)) }
+        }
+    }
+
+    /// Change the selection to the raw (utf-16) range `start..end`.
+    pub fn set_selection(&self, start: i32, end: i32) {
+        if let Some(f) = self.callbacks.handle_set_selection {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, start, end) }
+        }
+    }
+
+    /// Trigger an extended editing action, such as copy or paste.
+    pub fn extend_action(&self, action: ExtendAction) {
+        if let Some(f) = self.callbacks.handle_extend_action {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor, InputMethod_ExtendAction::from(action)) }
+        }
+    }
+
+    /// Query up to `number` utf-16 code units of text before the cursor.
+    pub fn text_before_cursor(&self, number: i32) -> String {
+        self.surrounding_text(self.callbacks.get_left_text_of_cursor, number)
+    }
+
+    /// Query up to `number` utf-16 code units of text after the cursor.
+    pub fn text_after_cursor(&self, number: i32) -> String {
+        self.surrounding_text(self.callbacks.get_right_text_of_cursor, number)
+    }
+
+    fn surrounding_text(
+        &self,
+        f: OH_TextEditorProxy_GetLeftTextOfCursorFunc,
+        number: i32,
+    ) -> String {
+        let Some(f) = f else {
+            return String::new();
+        };
+        let mut buf = vec![0_u16; number.max(0) as usize];
+        let mut length = buf.len();
+        // SAFETY: `buf` is valid for `length` elements.
+        unsafe { f(self.editor, number, buf.as_mut_ptr(), &raw mut length) };
+        buf.truncate(length);
+        String::from_utf16_lossy(&buf)
+    }
+
+    /// Query the cursor position in utf-16 code units.
+    pub fn cursor_index(&self) -> i32 {
+        match self.callbacks.get_text_index_at_cursor {
+            // SAFETY: The editor is alive for `'a`.
+            Some(f) => unsafe { f(self.editor) },
+            None => 0,
+        }
+    }
+
+    /// Show `text` as preview text, replacing the raw (utf-16) `range` if given.
+    ///
+    /// Returns the status code returned by the editor, `0` on success.
+    pub fn set_preview_text(&self, text: &str, range: Option<(i32, i32)>) -> i32 {
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        let (start, end) = range.unwrap_or((-1, -1));
+        match self.callbacks.set_preview_text {
+            // SAFETY: `utf16` is valid for `utf16.len()` elements.
+            Some(f) => unsafe { f(self.editor, utf16.as_ptr(), utf16.len(), start, end) },
+            None => -1,
+        }
+    }
+
+    /// Commit the current preview text.
+    pub fn finish_preview_text(&self) {
+        if let Some(f) = self.callbacks.finish_text_preview {
+            // SAFETY: The editor is alive for `'a`.
+            unsafe { f(self.editor) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{Attached, Event, RecordingIme};
+    use crate::TextSelection;
+
+    #[test]
+    fn typing_session() {
+        let attached = Attached::new(RecordingIme {
+            text_before_cursor: "Hello".to_string(),
+            cursor_index: 5,
+            ..RecordingIme::default()
+        });
+        let ime = FakeInputMethod::for_ime_proxy(&attached.proxy);
+        assert_eq!(ime.is_keyboard_shown(), Some(true));
+        ime.insert_text("Hi");
+        ime.delete_backward(1);
+        ime.send_enter_key(EnterKeyType::Send);
+        ime.send_keyboard_status(KeyboardStatus::Hidden);
+        ime.move_cursor(CursorDirection::Right);
+        ime.set_selection(0, 2);
+        ime.extend_action(ExtendAction::Copy);
+        assert_eq!(ime.set_preview_text("x", None), 0);
+        ime.finish_preview_text();
+        assert_eq!(ime.text_before_cursor(3), "llo");
+        assert_eq!(ime.text_after_cursor(3), "");
+        assert_eq!(ime.cursor_index(), 5);
+        assert_eq!(
+            attached.take_events(),
+            vec![
+                Event::InsertText("Hi".to_string()),
+                Event::DeleteBackward(1),
+                Event::SendEnterKey(EnterKeyType::Send),
+                Event::KeyboardStatusChanged(KeyboardStatus::Hidden),
+                Event::MoveCursor(CursorDirection::Right),
+                Event::SetSelection(TextSelection::new(0, 2).unwrap()),
+                Event::ExtendAction(ExtendAction::Copy),
+                Event::SetPreviewText("x".to_string(), None),
+                Event::FinishPreviewText,
+            ]
+        );
+        attached.proxy.hide_keyboard().unwrap();
+        assert_eq!(ime.is_keyboard_shown(), Some(false));
+    }
+
+    #[test]
+    fn unattached_editor() {
+        let editor = RawTextEditorProxy::new(Box::new(RecordingIme {
+            text_after_cursor: "world".to_string(),
+            ..RecordingIme::default()
+        }))
+        .unwrap();
+        let ime = FakeInputMethod::new(&editor);
+        assert_eq!(ime.is_keyboard_shown(), None);
+        assert_eq!(ime.text_after_cursor(10), "world");
+    }
+}
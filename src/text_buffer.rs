@@ -0,0 +1,471 @@
+//! A simple, ready to use text editor model.
+
+use crate::text_index::{self, delete_backward_range, delete_forward_range};
+use crate::{
+    CursorDirection, EnterKeyType, ExtendAction, Ime, TextConfig, TextInputType, TextSelection,
+};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Snapshot of the content of a [`TextBufferIme`].
+///
+/// All ranges are byte offsets into `text`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextBufferState {
+    pub text: String,
+    /// The selected text. The cursor is placed at `selection.end`, an empty selection is just a
+    /// cursor.
+    pub selection: Range<usize>,
+    /// The range of the preview (composing) text, if any.
+    pub preview: Option<Range<usize>>,
+}
+
+type ChangeListener = Arc<dyn Fn(&TextBufferState) + Send + Sync>;
+type EnterKeyListener = Arc<dyn Fn(EnterKeyType) + Send + Sync>;
+
+#[derive(Default)]
+struct Listeners {
+    on_change: Option<ChangeListener>,
+    on_enter_key: Option<EnterKeyListener>,
+}
+
+struct Inner {
+    config: TextConfig,
+    state: Mutex<TextBufferState>,
+    /// Text copied or cut via [`ExtendAction`]s.
+    clipboard: Mutex<String>,
+    listeners: Mutex<Listeners>,
+}
+
+/// An [`Ime`] implementation which owns a text buffer, cursor and selection.
+///
+/// This provides a complete input method integration for simple text fields. Clones share the
+/// same buffer, so one clone can be passed to [`RawTextEditorProxy::new()`] while the
+/// application keeps another one to read the text, or to get notified via
+/// [`TextBufferIme::on_change()`].
+///
+/// Copy, cut and paste operate on a buffer-internal clipboard. For single line input types,
+/// the enter key is reported via [`TextBufferIme::on_enter_key()`] instead of inserting a newline.
+///
+/// [`RawTextEditorProxy::new()`]: crate::RawTextEditorProxy::new
+#[derive(Clone)]
+pub struct TextBufferIme {
+    inner: Arc<Inner>,
+}
+
+impl TextBufferIme {
+    pub fn new(config: TextConfig) -> TextBufferIme {
+        TextBufferIme {
+            inner: Arc::new(Inner {
+                config,
+                state: Mutex::new(TextBufferState::default()),
+                clipboard: Mutex::new(String::new()),
+                listeners: Mutex::new(Listeners::default()),
+            }),
+        }
+    }
+
+    /// Return a snapshot of the current text, selection and preview text.
+    pub fn state(&self) -> TextBufferState {
+        self.lock_state().clone()
+    }
+
+    /// Return the current text.
+    pub fn text(&self) -> String {
+        self.lock_state().text.clone()
+    }
+
+    /// Replace the text and place the cursor at the end.
+    ///
+    /// This does not notify the input method. Use
+    /// [`ImeProxy::notify_selection_change()`](crate::ImeProxy::notify_selection_change) if the
+    /// buffer is attached.
+    pub fn set_text(&self, text: &str) {
+        self.modify(|state| {
+            state.text = text.to_string();
+            state.selection = text.len()..text.len();
+            state.preview = None;
+        });
+    }
+
+    /// Select the byte range `selection` of the text.
+    ///
+    /// The range is clamped to the text and rounded to `char` boundaries.
+    pub fn select(&self, selection: Range<usize>) {
+        self.modify(|state| {
+            let start = floor_char_boundary(&state.text, selection.start);
+            let end = floor_char_boundary(&state.text, selection.end).max(start);
+            state.selection = start..end;
+        });
+    }
+
+    /// Call `listener` with the new state whenever the buffer changes.
+    pub fn on_change(&self, listener: impl Fn(&TextBufferState) + Send + Sync + 'static) {
+        self.lock_listeners().on_change = Some(Arc::new(listener));
+    }
+
+    /// Call `listener` when the enter key is pressed in a single line text field.
+    pub fn on_enter_key(&self, listener: impl Fn(EnterKeyType) + Send + Sync + 'static) {
+        self.lock_listeners().on_enter_key = Some(Arc::new(listener));
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, TextBufferState> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_listeners(&self) -> MutexGuard<'_, Listeners> {
+        self.inner
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_clipboard(&self) -> MutexGuard<'_, String> {
+        self.inner
+            .clipboard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Apply `f` to the state and notify the change listener, if the state changed.
+    fn modify<R>(&self, f: impl FnOnce(&mut TextBufferState) -> R) -> R {
+        let (result, new_state) = {
+            let mut state = self.lock_state();
+            let old_state = state.clone();
+            let result = f(&mut state);
+            let changed = *state != old_state;
+            (result, changed.then(|| state.clone()))
+        };
+        // Call the listener without holding any locks, so it may access the buffer.
+        if let Some(new_state) = new_state {
+            let listener = self.lock_listeners().on_change.clone();
+            if let Some(listener) = listener {
+                listener(&new_state);
+            }
+        }
+        result
+    }
+
+    fn is_multiline(&self) -> bool {
+        self.inner.config.input_type == TextInputType::Multiline
+    }
+}
+
+impl Default for TextBufferIme {
+    fn default() -> TextBufferIme {
+        TextBufferIme::new(TextConfig::default())
+    }
+}
+
+impl TextBufferState {
+    /// Replace `range` with `text` and return the byte range of the inserted text.
+    fn replace(&mut self, range: Range<usize>, text: &str) -> Range<usize> {
+        self.text.replace_range(range.clone(), text);
+        range.start..range.start + text.len()
+    }
+
+    /// The range replaced by newly inserted text: The preview text if any, otherwise the selection.
+    fn composing_range(&self) -> Range<usize> {
+        self.preview
+            .clone()
+            .unwrap_or_else(|| self.selection.clone())
+    }
+
+    fn delete(&mut self, range: Range<usize>) {
+        self.text.replace_range(range.clone(), "");
+        self.selection = range.start..range.start;
+        self.preview = None;
+    }
+}
+
+/// Round `index` down to the closest `char` boundary of `text`, clamping it to `text.len()`.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Convert a utf-16 range into a byte range, rounding down to `char` boundaries.
+///
+/// [`TextSelection::new()`] accepts inverted ranges, so the start and end are ordered.
+fn utf16_to_byte_range(text: &str, range: TextSelection) -> Range<usize> {
+    let start = text_index::utf16_to_utf8_floor(text, range.start());
+    let end = text_index::utf16_to_utf8_floor(text, range.end());
+    start.min(end)..start.max(end)
+}
+
+impl Ime for TextBufferIme {
+    fn insert_text(&self, text: String) {
+        self.modify(|state| {
+            let range = state.composing_range();
+            let inserted = state.replace(range, &text);
+            state.selection = inserted.end..inserted.end;
+            state.preview = None;
+        });
+    }
+
+    fn delete_forward(&self, len: usize) {
+        self.modify(|state| {
+            let range = if state.selection.is_empty() {
+                let cursor = state.selection.end;
+                delete_forward_range(&state.text, cursor, len).unwrap_or(cursor..cursor)
+            } else {
+                state.selection.clone()
+            };
+            state.delete(range);
+        });
+    }
+
+    fn delete_backward(&self, len: usize) {
+        self.modify(|state| {
+            let range = if state.selection.is_empty() {
+                let cursor = state.selection.start;
+                delete_backward_range(&state.text, cursor, len).unwrap_or(cursor..cursor)
+            } else {
+                state.selection.clone()
+            };
+            state.delete(range);
+        });
+    }
+
+    fn get_text_config(&self) -> TextConfig {
+        let mut config = self.inner.config.clone();
+        let state = self.lock_state();
+        // Report the current selection, e.g. when the input method re-attaches.
+        config.selection = TextSelection::from_utf8_range(&state.text, state.selection.clone())
+            .ok()
+            .or(config.selection);
+        config
+    }
+
+    fn send_enter_key(&self, enter_key: EnterKeyType) {
+        if self.is_multiline() {
+            self.insert_text("\n".to_string());
+        } else {
+            let listener = self.lock_listeners().on_enter_key.clone();
+            if let Some(listener) = listener {
+                listener(enter_key);
+            }
+        }
+    }
+
+    fn move_cursor(&self, direction: CursorDirection) {
+        self.modify(|state| {
+            let selection = state.selection.clone();
+            let cursor = match direction {
+                CursorDirection::Left if selection.is_empty() => {
+                    delete_backward_range(&state.text, selection.start, 1)
+                        .map_or(selection.start, |range| range.start)
+                }
+                CursorDirection::Left => selection.start,
+                CursorDirection::Right if selection.is_empty() => {
+                    delete_forward_range(&state.text, selection.end, 1)
+                        .map_or(selection.end, |range| range.end)
+                }
+                CursorDirection::Right => selection.end,
+                // There is no layout information, so treat the text as a single line.
+                CursorDirection::Up => 0,
+                CursorDirection::Down => state.text.len(),
+                CursorDirection::None | CursorDirection::Unknown(_) => return,
+            };
+            state.selection = cursor..cursor;
+        });
+    }
+
+    fn set_selection(&self, range: TextSelection) {
+        self.modify(|state| {
+            state.selection = utf16_to_byte_range(&state.text, range);
+        });
+    }
+
+    fn handle_extend_action(&self, action: ExtendAction) {
+        match action {
+            ExtendAction::SelectAll => self.modify(|state| state.selection = 0..state.text.len()),
+            ExtendAction::Copy => {
+                let state = self.lock_state();
+                *self.lock_clipboard() = state.text[state.selection.clone()].to_string();
+            }
+            ExtendAction::Cut => {
+                let cut = self.modify(|state| {
+                    let selection = state.selection.clone();
+                    let cut = state.text[selection.clone()].to_string();
+                    state.delete(selection);
+                    cut
+                });
+                *self.lock_clipboard() = cut;
+            }
+            ExtendAction::Paste => {
+                let text = self.lock_clipboard().clone();
+                self.insert_text(text);
+            }
+            ExtendAction::Unknown(_) => {}
+        }
+    }
+
+    fn text_before_cursor(&self, n: usize) -> String {
+        let state = self.lock_state();
+        let cursor = state.selection.start;
+        // Like deleting, never split a grapheme cluster.
+        let range = delete_backward_range(&state.text, cursor, n).unwrap_or(cursor..cursor);
+        state.text[range].to_string()
+    }
+
+    fn text_after_cursor(&self, n: usize) -> String {
+        let state = self.lock_state();
+        let cursor = state.selection.end;
+        let range = delete_forward_range(&state.text, cursor, n).unwrap_or(cursor..cursor);
+        state.text[range].to_string()
+    }
+
+    fn cursor_index(&self) -> usize {
+        let state = self.lock_state();
+        // The selection is always on `char` boundaries.
+        text_index::utf8_to_utf16(&state.text, state.selection.end).unwrap_or(0)
+    }
+
+    fn set_preview_text(&self, text: String, range: Option<TextSelection>) {
+        self.modify(|state| {
+            let range = match range {
+                Some(range) => utf16_to_byte_range(&state.text, range),
+                None => state.composing_range(),
+            };
+            let preview = state.replace(range, &text);
+            state.selection = preview.end..preview.end;
+            state.preview = Some(preview);
+        });
+    }
+
+    fn finish_preview_text(&self) {
+        self.modify(|state| state.preview = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeInputMethod;
+    use crate::{AttachOptions, ImeProxy, RawTextEditorProxy, TextConfigBuilder};
+
+    fn attach(buffer: &TextBufferIme) -> ImeProxy {
+        let editor = RawTextEditorProxy::new(Box::new(buffer.clone())).unwrap();
+        ImeProxy::new(editor, AttachOptions::new(true)).unwrap()
+    }
+
+    #[test]
+    fn typing_and_deleting() {
+        let buffer = TextBufferIme::default();
+        let proxy = attach(&buffer);
+        let ime = FakeInputMethod::for_ime_proxy(&proxy);
+        ime.insert_text("Hello 😀");
+        assert_eq!(ime.cursor_index(), 8);
+        ime.delete_backward(2);
+        assert_eq!(buffer.text(), "Hello ");
+        // Deleting part of a grapheme cluster deletes all of it.
+        ime.insert_text("e\u{301}");
+        assert_eq!(Ime::text_before_cursor(&buffer, 1), "e\u{301}");
+        ime.delete_backward(1);
+        assert_eq!(buffer.text(), "Hello ");
+        ime.move_cursor(CursorDirection::Up);
+        ime.delete_forward(1);
+        assert_eq!(buffer.text(), "ello ");
+        assert_eq!(ime.text_after_cursor(3), "ell");
+        ime.move_cursor(CursorDirection::Down);
+        assert_eq!(ime.text_before_cursor(2), "o ");
+    }
+
+    #[test]
+    fn selection_and_clipboard() {
+        let buffer = TextBufferIme::default();
+        buffer.set_text("ä b c");
+        let proxy = attach(&buffer);
+        let ime = FakeInputMethod::for_ime_proxy(&proxy);
+        ime.set_selection(0, 1);
+        assert_eq!(buffer.state().selection, 0..2);
+        ime.extend_action(ExtendAction::Cut);
+        assert_eq!(buffer.text(), " b c");
+        ime.move_cursor(CursorDirection::Down);
+        ime.extend_action(ExtendAction::Paste);
+        assert_eq!(buffer.text(), " b cä");
+        ime.extend_action(ExtendAction::SelectAll);
+        ime.insert_text("x");
+        assert_eq!(buffer.text(), "x");
+        // Inverted ranges are ordered.
+        buffer.set_text("abc");
+        Ime::set_selection(&buffer, TextSelection::new(3, 1).unwrap());
+        assert_eq!(buffer.state().selection, 1..3);
+        ime.extend_action(ExtendAction::Copy);
+        ime.extend_action(ExtendAction::Paste);
+        assert_eq!(buffer.text(), "abc");
+    }
+
+    #[test]
+    fn preview_text() {
+        let buffer = TextBufferIme::default();
+        buffer.set_text("a");
+        let proxy = attach(&buffer);
+        let ime = FakeInputMethod::for_ime_proxy(&proxy);
+        ime.set_preview_text("n", None);
+        ime.set_preview_text("ni", None);
+        assert_eq!(buffer.state().preview, Some(1..3));
+        ime.insert_text("你");
+        assert_eq!(buffer.state().preview, None);
+        assert_eq!(buffer.text(), "a你");
+        ime.set_preview_text("b", Some((0, 1)));
+        ime.finish_preview_text();
+        assert_eq!(buffer.text(), "b你");
+        assert_eq!(buffer.state().selection, 1..1);
+        Ime::set_preview_text(&buffer, "c".to_string(), TextSelection::new(1, 0).ok());
+        assert_eq!(buffer.text(), "c你");
+        assert_eq!(buffer.state().preview, Some(0..1));
+    }
+
+    #[test]
+    fn enter_key_and_change_notifications() {
+        let single_line = TextBufferIme::default();
+        let entered = Arc::new(Mutex::new(Vec::new()));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let entered_clone = entered.clone();
+        single_line.on_enter_key(move |key| entered_clone.lock().unwrap().push(key));
+        let changes_clone = changes.clone();
+        single_line.on_change(move |state| changes_clone.lock().unwrap().push(state.text.clone()));
+        let proxy = attach(&single_line);
+        let ime = FakeInputMethod::for_ime_proxy(&proxy);
+        ime.insert_text("a");
+        ime.send_enter_key(EnterKeyType::Go);
+        ime.move_cursor(CursorDirection::Right);
+        assert_eq!(single_line.text(), "a");
+        assert_eq!(*entered.lock().unwrap(), vec![EnterKeyType::Go]);
+        // Moving the cursor at the end of the text does not change anything.
+        assert_eq!(*changes.lock().unwrap(), vec!["a".to_string()]);
+
+        let multi_line = TextBufferIme::new(
+            TextConfigBuilder::new()
+                .input_type(TextInputType::Multiline)
+                .build(),
+        );
+        let proxy = attach(&multi_line);
+        let ime = FakeInputMethod::for_ime_proxy(&proxy);
+        ime.send_enter_key(EnterKeyType::Newline);
+        assert_eq!(multi_line.text(), "\n");
+    }
+
+    #[test]
+    fn text_config_reports_current_selection() {
+        let buffer = TextBufferIme::default();
+        assert_eq!(
+            buffer.get_text_config().selection,
+            Some(TextSelection::new(0, 0).unwrap())
+        );
+        buffer.set_text("😀ab");
+        buffer.select(4..5);
+        assert_eq!(
+            buffer.get_text_config().selection,
+            Some(TextSelection::new(2, 3).unwrap())
+        );
+    }
+}
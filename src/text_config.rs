@@ -1,16 +1,30 @@
+use crate::text_index::{self, IndexError};
+use std::fmt::{Display, Formatter};
 use std::num::TryFromIntError;
+use std::ops::Range;
 // use std::ptr::NonNull;
 // use ohos_ime_sys::text_config::{InputMethod_TextConfig, OH_TextConfig_Create, OH_TextConfig_Destroy, OH_TextConfig_SetEnterKeyType, OH_TextConfig_SetInputType, OH_TextConfig_SetPreviewTextSupport, OH_TextConfig_SetSelection, OH_TextConfig_SetWindowId};
+use log::warn;
 use ohos_ime_sys::types::{InputMethod_EnterKeyType, InputMethod_TextInputType};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TextSelection {
     pub(crate) start: i32,
     pub(crate) end: i32,
 }
 
+/// The selection indices are too large for the inputmethod API.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InvalidSelection(());
 
+impl Display for InvalidSelection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("invalid text selection")
+    }
+}
+
+impl std::error::Error for InvalidSelection {}
+
 impl From<TryFromIntError> for InvalidSelection {
     fn from(_: TryFromIntError) -> Self {
         InvalidSelection(())
@@ -18,25 +32,283 @@ impl From<TryFromIntError> for InvalidSelection {
 }
 
 impl TextSelection {
-    // Todo: Since we have utf-8 rust strings, but use utf-16 on the arkts side, do the indexes perhaps
-    // need to be updated for cases where 2 utf-8 codepoints map to 1 utf-16 codepoint?
-    // But we don't have any information about the string here, so I guess we would need to
-    // impose this as a usage requirement on the user.
     /// Create a new Text Selection.
+    ///
+    /// `start` and `end` are utf-16 code unit indices, as used by the inputmethod API. Use
+    /// [`TextSelection::from_utf8_range()`] to create a selection from byte offsets into a Rust
+    /// string.
     pub fn new(start: usize, end: usize) -> Result<TextSelection, InvalidSelection> {
         Ok(TextSelection {
             start: start.try_into()?,
             end: end.try_into()?,
         })
     }
+
+    fn from_utf16_indices(start: usize, end: usize) -> Result<TextSelection, IndexError> {
+        if start > end {
+            return Err(IndexError::InvertedRange);
+        }
+        Ok(TextSelection {
+            start: start.try_into().map_err(|_| IndexError::TooLarge)?,
+            end: end.try_into().map_err(|_| IndexError::TooLarge)?,
+        })
+    }
+
+    /// Create a selection from the byte range `range` of `text`.
+    ///
+    /// Fails if the range is inverted, out of bounds or splits a code point.
+    pub fn from_utf8_range(text: &str, range: Range<usize>) -> Result<TextSelection, IndexError> {
+        if range.start > range.end {
+            return Err(IndexError::InvertedRange);
+        }
+        let start = text_index::utf8_to_utf16(text, range.start)?;
+        let end = text_index::utf8_to_utf16(text, range.end)?;
+        TextSelection::from_utf16_indices(start, end)
+    }
+
+    /// The selected byte range of `text`.
+    ///
+    /// Fails if the selection is out of bounds or splits a surrogate pair.
+    pub fn to_utf8_range(&self, text: &str) -> Result<Range<usize>, IndexError> {
+        let start = text_index::utf16_to_utf8(text, self.start())?;
+        let end = text_index::utf16_to_utf8(text, self.end())?;
+        Ok(start..end)
+    }
+
+    /// Create a selection from the range `range` of `char`s of `text`.
+    pub fn from_char_range(text: &str, range: Range<usize>) -> Result<TextSelection, IndexError> {
+        if range.start > range.end {
+            return Err(IndexError::InvertedRange);
+        }
+        let start = text_index::char_to_utf8(text, range.start)?;
+        let end = text_index::char_to_utf8(text, range.end)?;
+        TextSelection::from_utf8_range(text, start..end)
+    }
+
+    /// The selected range of `char`s of `text`.
+    pub fn to_char_range(&self, text: &str) -> Result<Range<usize>, IndexError> {
+        let range = self.to_utf8_range(text)?;
+        let start = text_index::utf8_to_char(text, range.start)?;
+        let end = text_index::utf8_to_char(text, range.end)?;
+        Ok(start..end)
+    }
+
+    /// Create a selection from the range `range` of extended grapheme clusters of `text`.
+    pub fn from_grapheme_range(
+        text: &str,
+        range: Range<usize>,
+    ) -> Result<TextSelection, IndexError> {
+        if range.start > range.end {
+            return Err(IndexError::InvertedRange);
+        }
+        let start = text_index::grapheme_to_utf8(text, range.start)?;
+        let end = text_index::grapheme_to_utf8(text, range.end)?;
+        TextSelection::from_utf8_range(text, start..end)
+    }
+
+    /// The selected range of extended grapheme clusters of `text`.
+    ///
+    /// Fails if the selection splits a grapheme cluster.
+    pub fn to_grapheme_range(&self, text: &str) -> Result<Range<usize>, IndexError> {
+        let range = self.to_utf8_range(text)?;
+        let start = text_index::utf8_to_grapheme(text, range.start)?;
+        let end = text_index::utf8_to_grapheme(text, range.end)?;
+        Ok(start..end)
+    }
+
+    /// Create a selection from the raw indices passed by the input method.
+    ///
+    /// Negative indices and ranges where `start > end` are rejected.
+    pub(crate) fn from_raw(start: i32, end: i32) -> Result<TextSelection, InvalidSelection> {
+        if start < 0 || end < 0 || start > end {
+            return Err(InvalidSelection(()));
+        }
+        Ok(TextSelection { start, end })
+    }
+
+    /// The start index of the selection.
+    pub fn start(&self) -> usize {
+        // Invariant: start is never negative.
+        self.start as usize
+    }
+
+    /// The end index of the selection (exclusive).
+    pub fn end(&self) -> usize {
+        // Invariant: end is never negative.
+        self.end as usize
+    }
+}
+
+/// `IME_TEXT_INPUT_TYPE_ONE_TIME_CODE`, which `ohos-ime-sys` only defines with `api-20`.
+const ONE_TIME_CODE: InputMethod_TextInputType = InputMethod_TextInputType(13);
+
+/// The type of text a text field accepts, which determines the keyboard layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextInputType {
+    None,
+    Text,
+    Multiline,
+    Number,
+    Phone,
+    Datetime,
+    Email,
+    Url,
+    VisiblePassword,
+    NumberPassword,
+    ScreenLockPassword,
+    UserName,
+    NewPassword,
+    NumberDecimal,
+    /// A one time code, e.g. received via SMS. Available since API level 20.
+    OneTimeCode,
+    Unknown(i32),
+}
+
+impl From<InputMethod_TextInputType> for TextInputType {
+    fn from(input_type: InputMethod_TextInputType) -> Self {
+        match input_type {
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NONE => TextInputType::None,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_TEXT => TextInputType::Text,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_MULTILINE => TextInputType::Multiline,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER => TextInputType::Number,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_PHONE => TextInputType::Phone,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_DATETIME => TextInputType::Datetime,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_EMAIL_ADDRESS => TextInputType::Email,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_URL => TextInputType::Url,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_VISIBLE_PASSWORD => {
+                TextInputType::VisiblePassword
+            }
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER_PASSWORD => {
+                TextInputType::NumberPassword
+            }
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_SCREEN_LOCK_PASSWORD => {
+                TextInputType::ScreenLockPassword
+            }
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_USER_NAME => TextInputType::UserName,
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NEW_PASSWORD => {
+                TextInputType::NewPassword
+            }
+            InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER_DECIMAL => {
+                TextInputType::NumberDecimal
+            }
+            ONE_TIME_CODE => TextInputType::OneTimeCode,
+            input_type => {
+                warn!("Unknown text input type enum variant: {}", input_type.0);
+                TextInputType::Unknown(input_type.0)
+            }
+        }
+    }
+}
+
+impl From<TextInputType> for InputMethod_TextInputType {
+    fn from(input_type: TextInputType) -> Self {
+        match input_type {
+            TextInputType::None => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NONE,
+            TextInputType::Text => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_TEXT,
+            TextInputType::Multiline => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_MULTILINE,
+            TextInputType::Number => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER,
+            TextInputType::Phone => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_PHONE,
+            TextInputType::Datetime => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_DATETIME,
+            TextInputType::Email => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_EMAIL_ADDRESS,
+            TextInputType::Url => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_URL,
+            TextInputType::VisiblePassword => {
+                InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_VISIBLE_PASSWORD
+            }
+            TextInputType::NumberPassword => {
+                InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER_PASSWORD
+            }
+            TextInputType::ScreenLockPassword => {
+                InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_SCREEN_LOCK_PASSWORD
+            }
+            TextInputType::UserName => InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_USER_NAME,
+            TextInputType::NewPassword => {
+                InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NEW_PASSWORD
+            }
+            TextInputType::NumberDecimal => {
+                InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER_DECIMAL
+            }
+            TextInputType::OneTimeCode => ONE_TIME_CODE,
+            TextInputType::Unknown(raw) => InputMethod_TextInputType(raw),
+        }
+    }
 }
 
+/// The label of the enter key on the virtual keyboard.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EnterKeyType {
+    Unspecified,
+    None,
+    Go,
+    Search,
+    Send,
+    Next,
+    Done,
+    Previous,
+    Newline,
+    Unknown(u32),
+}
+
+impl From<InputMethod_EnterKeyType> for EnterKeyType {
+    fn from(enter_key: InputMethod_EnterKeyType) -> Self {
+        match enter_key {
+            InputMethod_EnterKeyType::IME_ENTER_KEY_UNSPECIFIED => EnterKeyType::Unspecified,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_NONE => EnterKeyType::None,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_GO => EnterKeyType::Go,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_SEARCH => EnterKeyType::Search,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_SEND => EnterKeyType::Send,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_NEXT => EnterKeyType::Next,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_DONE => EnterKeyType::Done,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_PREVIOUS => EnterKeyType::Previous,
+            InputMethod_EnterKeyType::IME_ENTER_KEY_NEWLINE => EnterKeyType::Newline,
+            enter_key => {
+                warn!("Unknown enter key type enum variant: {}", enter_key.0);
+                EnterKeyType::Unknown(enter_key.0)
+            }
+        }
+    }
+}
+
+impl From<EnterKeyType> for InputMethod_EnterKeyType {
+    fn from(enter_key: EnterKeyType) -> Self {
+        match enter_key {
+            EnterKeyType::Unspecified => InputMethod_EnterKeyType::IME_ENTER_KEY_UNSPECIFIED,
+            EnterKeyType::None => InputMethod_EnterKeyType::IME_ENTER_KEY_NONE,
+            EnterKeyType::Go => InputMethod_EnterKeyType::IME_ENTER_KEY_GO,
+            EnterKeyType::Search => InputMethod_EnterKeyType::IME_ENTER_KEY_SEARCH,
+            EnterKeyType::Send => InputMethod_EnterKeyType::IME_ENTER_KEY_SEND,
+            EnterKeyType::Next => InputMethod_EnterKeyType::IME_ENTER_KEY_NEXT,
+            EnterKeyType::Done => InputMethod_EnterKeyType::IME_ENTER_KEY_DONE,
+            EnterKeyType::Previous => InputMethod_EnterKeyType::IME_ENTER_KEY_PREVIOUS,
+            EnterKeyType::Newline => InputMethod_EnterKeyType::IME_ENTER_KEY_NEWLINE,
+            EnterKeyType::Unknown(raw) => InputMethod_EnterKeyType(raw),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct TextConfig {
-    pub(crate) input_type: InputMethod_TextInputType,
-    pub(crate) enterkey_type: InputMethod_EnterKeyType,
+    pub(crate) input_type: TextInputType,
+    pub(crate) enterkey_type: EnterKeyType,
     pub(crate) preview_text_support: bool,
     pub(crate) selection: Option<TextSelection>,
     pub(crate) window_id: Option<i32>,
+    pub(crate) avoid_info: Option<(f64, f64)>,
+    #[cfg(feature = "api-20")]
+    pub(crate) placeholder: Option<String>,
+    #[cfg(feature = "api-20")]
+    pub(crate) ability_name: Option<String>,
+}
+
+impl TextConfig {
+    /// The input type as passed to the inputmethod API.
+    pub(crate) fn raw_input_type(&self) -> InputMethod_TextInputType {
+        self.input_type.into()
+    }
+
+    /// The enter key type as passed to the inputmethod API.
+    pub(crate) fn raw_enterkey_type(&self) -> InputMethod_EnterKeyType {
+        self.enterkey_type.into()
+    }
 }
 
 impl Default for TextConfig {
@@ -46,21 +318,31 @@ impl Default for TextConfig {
 }
 
 pub struct TextConfigBuilder {
-    input_type: InputMethod_TextInputType,
-    enterkey_type: InputMethod_EnterKeyType,
+    input_type: TextInputType,
+    enterkey_type: EnterKeyType,
     preview_text_support: bool,
     selection: Option<TextSelection>,
     window_id: Option<i32>,
+    avoid_info: Option<(f64, f64)>,
+    #[cfg(feature = "api-20")]
+    placeholder: Option<String>,
+    #[cfg(feature = "api-20")]
+    ability_name: Option<String>,
 }
 
 impl TextConfigBuilder {
     pub const fn new() -> TextConfigBuilder {
         TextConfigBuilder {
-            input_type: InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_TEXT,
-            enterkey_type: InputMethod_EnterKeyType::IME_ENTER_KEY_UNSPECIFIED,
+            input_type: TextInputType::Text,
+            enterkey_type: EnterKeyType::Unspecified,
             preview_text_support: false,
             selection: None,
             window_id: None,
+            avoid_info: None,
+            #[cfg(feature = "api-20")]
+            placeholder: None,
+            #[cfg(feature = "api-20")]
+            ability_name: None,
         }
     }
 
@@ -72,15 +354,20 @@ impl TextConfigBuilder {
             preview_text_support: self.preview_text_support,
             selection: self.selection.clone(),
             window_id: self.window_id,
+            avoid_info: self.avoid_info,
+            #[cfg(feature = "api-20")]
+            placeholder: self.placeholder.clone(),
+            #[cfg(feature = "api-20")]
+            ability_name: self.ability_name.clone(),
         }
     }
 
-    pub fn input_type(mut self, input_type: InputMethod_TextInputType) -> TextConfigBuilder {
+    pub fn input_type(mut self, input_type: TextInputType) -> TextConfigBuilder {
         self.input_type = input_type;
         self
     }
 
-    pub fn enterkey_type(mut self, enterkey_type: InputMethod_EnterKeyType) -> TextConfigBuilder {
+    pub fn enterkey_type(mut self, enterkey_type: EnterKeyType) -> TextConfigBuilder {
         self.enterkey_type = enterkey_type;
         self
     }
@@ -99,6 +386,34 @@ impl TextConfigBuilder {
         self.window_id = Some(window_id);
         self
     }
+
+    /// The area the keyboard panel should avoid covering, typically the focused text field.
+    ///
+    /// `position_y` is the y coordinate of the area and `height` its height, both in px relative
+    /// to the window.
+    pub fn avoid_info(mut self, position_y: f64, height: f64) -> TextConfigBuilder {
+        self.avoid_info = Some((position_y, height));
+        self
+    }
+
+    /// The placeholder text shown by the input method while the text field is empty.
+    ///
+    /// The input method accepts at most 255 utf-16 code units, longer text is truncated.
+    #[cfg(feature = "api-20")]
+    pub fn placeholder(mut self, placeholder: &str) -> TextConfigBuilder {
+        self.placeholder = Some(placeholder.to_owned());
+        self
+    }
+
+    /// The name of the ability owning the text field, which input methods may use e.g. to adapt
+    /// their suggestions per application.
+    ///
+    /// The input method accepts at most 127 utf-16 code units, longer names are truncated.
+    #[cfg(feature = "api-20")]
+    pub fn ability_name(mut self, ability_name: &str) -> TextConfigBuilder {
+        self.ability_name = Some(ability_name.to_owned());
+        self
+    }
 }
 
 impl Default for TextConfigBuilder {
@@ -1,126 +1,126 @@
 // TODO:
 // - switch to parking lot and uns MutexGuard::map or owning_ref to reduce some of duplicate code here.
 #![allow(unused)]
-pub use crate::text_config::{TextConfig, TextConfigBuilder};
-use crate::{Ime, KeyboardStatus};
-use log::{debug, error, info, trace, warn};
-use ohos_ime_sys::private_command::InputMethod_PrivateCommand;
-use ohos_ime_sys::text_config::{
-    InputMethod_TextConfig, OH_TextConfig_SetEnterKeyType, OH_TextConfig_SetInputType,
-    OH_TextConfig_SetPreviewTextSupport, OH_TextConfig_SetSelection, OH_TextConfig_SetWindowId,
+use crate::dispatcher::DISPATCHER;
+use crate::error::ErrorCode;
+use crate::ffi::private_command::InputMethod_PrivateCommand;
+use crate::ffi::text_avoid_info::{OH_TextAvoidInfo_SetHeight, OH_TextAvoidInfo_SetPositionY};
+use crate::ffi::text_config::{
+    InputMethod_TextConfig, OH_TextConfig_GetTextAvoidInfo, OH_TextConfig_SetEnterKeyType,
+    OH_TextConfig_SetInputType, OH_TextConfig_SetPreviewTextSupport, OH_TextConfig_SetSelection,
+    OH_TextConfig_SetWindowId,
+};
+#[cfg(feature = "api-20")]
+use crate::ffi::text_config::{OH_TextConfig_SetAbilityName, OH_TextConfig_SetPlaceholder};
+use crate::ffi::text_editor_proxy::InputMethod_TextEditorProxy;
+pub use crate::text_config::{EnterKeyType, TextConfig, TextConfigBuilder, TextSelection};
+#[cfg(feature = "api-20")]
+use crate::text_index::to_utf16_with_nul;
+use crate::{
+    CursorDirection, ExtendAction, Ime, KeyboardStatus, PrivateCommand, PrivateCommandError,
 };
-use ohos_ime_sys::text_editor_proxy::InputMethod_TextEditorProxy;
+use log::{debug, error, info, trace, warn};
 use ohos_ime_sys::types::{
-    InputMethod_Direction, InputMethod_EnterKeyType, InputMethod_ExtendAction,
-    InputMethod_KeyboardStatus,
+    InputMethodErrorCode, InputMethod_Direction, InputMethod_EnterKeyType,
+    InputMethod_ExtendAction, InputMethod_KeyboardStatus,
 };
-use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::ptr::{slice_from_raw_parts, NonNull};
-use std::sync::{RwLock, RwLockReadGuard};
-
-pub(crate) static DISPATCHER: Dispatcher = Dispatcher::new();
 
-#[derive(Debug)]
-pub(crate) enum DispatcherError {
-    Uninitialized,
-    NotFound,
-    LockPoisoned,
-}
-
-pub(crate) struct Dispatcher {
-    map: RwLock<Option<HashMap<usize, Box<dyn super::Ime>>>>,
+/// Applying the [`TextConfig`] returned by [`Ime::get_text_config()`] failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+#[non_exhaustive]
+pub enum ApplyTextConfigError {
+    SetInputTypeFailed(InputMethodErrorCode),
+    SetEnterKeyTypeFailed(InputMethodErrorCode),
+    SetPreviewTextSupportFailed(InputMethodErrorCode),
+    SetSelectionFailed(InputMethodErrorCode),
+    SetWindowIdFailed(InputMethodErrorCode),
+    SetAvoidInfoFailed(InputMethodErrorCode),
+    #[cfg(feature = "api-20")]
+    SetPlaceholderFailed(InputMethodErrorCode),
+    #[cfg(feature = "api-20")]
+    SetAbilityNameFailed(InputMethodErrorCode),
 }
 
-// todo: proper error handling, propogation. etc.
-impl Dispatcher {
-    const fn new() -> Self {
-        Self {
-            map: RwLock::new(None),
-        }
-    }
-
-    pub(crate) fn register(
-        &self,
-        c_proxy: NonNull<InputMethod_TextEditorProxy>,
-        ime: Box<dyn Ime>,
-    ) {
-        debug!("Registering IME");
-        // Todo: remove unwrap and make register() fallible.
-        let mut map = self.map.write().unwrap();
-        let res = map
-            .get_or_insert_with(HashMap::new)
-            .insert(c_proxy.as_ptr() as usize, ime);
-        if res.is_some() {
-            warn!("Double insert of IME text editor. Dropping the old one");
-        }
-    }
-
-    pub(crate) fn unregister(
-        &self,
-        c_proxy: NonNull<InputMethod_TextEditorProxy>,
-    ) -> Result<Box<dyn Ime>, DispatcherError> {
-        debug!("Unregistering IME");
-        let mut map = self
-            .map
-            .write()
-            .map_err(|_| DispatcherError::LockPoisoned)?;
-        map.as_mut()
-            .ok_or(DispatcherError::Uninitialized)?
-            .remove(&(c_proxy.as_ptr() as usize))
-            .ok_or(DispatcherError::NotFound)
-    }
-
-    /// Helper function to dispatch a closure to the IME implementation.
-    fn dispatch(
-        &self,
-        text_editor_proxy: *mut InputMethod_TextEditorProxy,
-        f: impl FnOnce(&Box<dyn Ime>),
-    ) {
-        let map = self.map.read().unwrap();
-        let ime = map
-            .as_ref()
-            .and_then(|m| m.get(&(text_editor_proxy as usize)));
-        match ime {
-            Some(ime) => f(ime),
-            None => {
-                error!("IME dispatcher called, but no IME implementation registered!")
+impl Display for ApplyTextConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (field, code) = match self {
+            ApplyTextConfigError::SetInputTypeFailed(code) => ("input type", code),
+            ApplyTextConfigError::SetEnterKeyTypeFailed(code) => ("enter key type", code),
+            ApplyTextConfigError::SetPreviewTextSupportFailed(code) => {
+                ("preview text support", code)
             }
-        }
+            ApplyTextConfigError::SetSelectionFailed(code) => ("selection", code),
+            ApplyTextConfigError::SetWindowIdFailed(code) => ("window id", code),
+            ApplyTextConfigError::SetAvoidInfoFailed(code) => ("avoid info", code),
+            #[cfg(feature = "api-20")]
+            ApplyTextConfigError::SetPlaceholderFailed(code) => ("placeholder", code),
+            #[cfg(feature = "api-20")]
+            ApplyTextConfigError::SetAbilityNameFailed(code) => ("ability name", code),
+        };
+        write!(
+            f,
+            "setting the {field} of the text config failed: {}",
+            ErrorCode(*code)
+        )
     }
 }
 
-#[derive(Debug)]
-#[allow(clippy::enum_variant_names)]
-pub enum ApplyTextConfigError {
-    SetInputTypeFailed,
-    SetEnterKeyTypeFailed,
-    SetPreviewTextSupportFailed,
-    SetSelectioFailed,
-    SetWindowIdFailed,
-}
+impl std::error::Error for ApplyTextConfigError {}
+
+/// Maximum length of the placeholder in utf-16 code units, including the NUL terminator.
+#[cfg(feature = "api-20")]
+const MAX_PLACEHOLDER_LEN: usize = 256;
+/// Maximum length of the ability name in utf-16 code units, including the NUL terminator.
+#[cfg(feature = "api-20")]
+const MAX_ABILITY_NAME_LEN: usize = 128;
 
 fn apply_text_config(
     config: &TextConfig,
     oh_config: *mut InputMethod_TextConfig,
 ) -> Result<(), ApplyTextConfigError> {
     unsafe {
-        OH_TextConfig_SetInputType(oh_config, config.input_type)
-            .map_err(|_e| ApplyTextConfigError::SetInputTypeFailed)?;
+        OH_TextConfig_SetInputType(oh_config, config.raw_input_type())
+            .map_err(ApplyTextConfigError::SetInputTypeFailed)?;
 
-        OH_TextConfig_SetEnterKeyType(oh_config, config.enterkey_type)
-            .map_err(|_e| ApplyTextConfigError::SetEnterKeyTypeFailed)?;
+        OH_TextConfig_SetEnterKeyType(oh_config, config.raw_enterkey_type())
+            .map_err(ApplyTextConfigError::SetEnterKeyTypeFailed)?;
 
         OH_TextConfig_SetPreviewTextSupport(oh_config, config.preview_text_support)
-            .map_err(|_e| ApplyTextConfigError::SetPreviewTextSupportFailed)?;
+            .map_err(ApplyTextConfigError::SetPreviewTextSupportFailed)?;
 
         if let Some(selection) = &config.selection {
             OH_TextConfig_SetSelection(oh_config, selection.start, selection.end)
-                .map_err(|_e| ApplyTextConfigError::SetSelectioFailed);
+                .map_err(ApplyTextConfigError::SetSelectionFailed)?;
         }
         if let Some(window_id) = config.window_id {
             // let's see if this is optional...
             OH_TextConfig_SetWindowId(oh_config, window_id)
-                .map_err(|_e| ApplyTextConfigError::SetWindowIdFailed)?;
+                .map_err(ApplyTextConfigError::SetWindowIdFailed)?;
+        }
+        if let Some((position_y, height)) = config.avoid_info {
+            // The avoid info is owned by the text config.
+            let mut avoid_info = std::ptr::null_mut();
+            OH_TextConfig_GetTextAvoidInfo(oh_config, &mut avoid_info)
+                .map_err(ApplyTextConfigError::SetAvoidInfoFailed)?;
+            OH_TextAvoidInfo_SetPositionY(avoid_info, position_y)
+                .map_err(ApplyTextConfigError::SetAvoidInfoFailed)?;
+            OH_TextAvoidInfo_SetHeight(avoid_info, height)
+                .map_err(ApplyTextConfigError::SetAvoidInfoFailed)?;
+        }
+        #[cfg(feature = "api-20")]
+        if let Some(placeholder) = &config.placeholder {
+            let placeholder = to_utf16_with_nul(placeholder, MAX_PLACEHOLDER_LEN);
+            OH_TextConfig_SetPlaceholder(oh_config, placeholder.as_ptr(), placeholder.len())
+                .map_err(ApplyTextConfigError::SetPlaceholderFailed)?;
+        }
+        #[cfg(feature = "api-20")]
+        if let Some(ability_name) = &config.ability_name {
+            let ability_name = to_utf16_with_nul(ability_name, MAX_ABILITY_NAME_LEN);
+            OH_TextConfig_SetAbilityName(oh_config, ability_name.as_ptr(), ability_name.len())
+                .map_err(ApplyTextConfigError::SetAbilityNameFailed)?;
         }
     }
     Ok(())
@@ -131,10 +131,10 @@ pub extern "C" fn get_text_config(
     out_config: *mut InputMethod_TextConfig,
 ) {
     info!("get_text_config: Getting IME text config");
-    DISPATCHER.dispatch(text_editor_proxy, |ime| {
+    DISPATCHER.dispatch("get_text_config", text_editor_proxy, |ime| {
         let config = ime.get_text_config();
-        if let Err(e) = apply_text_config(config, out_config) {
-            error!("Failed to apply IME config in `get_text_config`: {e:?}");
+        if let Err(e) = apply_text_config(&config, out_config) {
+            error!("Failed to apply IME config in `get_text_config`: {e}");
         }
     });
 }
@@ -149,7 +149,7 @@ pub extern "C" fn insert_text(
         let utf16_str = slice_from_raw_parts(text, length);
         // SAFETY: We trust the OH APIs to give us a valid u16 slice
         if let Some(slice) = unsafe { utf16_str.as_ref() } {
-            DISPATCHER.dispatch(text_editor_proxy, |ime| {
+            DISPATCHER.dispatch("insert_text", text_editor_proxy, |ime| {
                 let rust_string = String::from_utf16(slice);
                 match rust_string {
                     Ok(s) => {
@@ -159,9 +159,6 @@ pub extern "C" fn insert_text(
                         error!("IME `insert_text` received malformed utf-16 string: {e:?} ");
                     }
                 }
-
-                let rust_text = String::new();
-                ime.insert_text(rust_text)
             });
         } else {
             #[cfg(debug_assertions)]
@@ -175,7 +172,7 @@ pub extern "C" fn delete_forward(text_editor_proxy: *mut InputMethod_TextEditorP
     if length < 0 {
         log::warn!("delete_forward called with negative length: {}", length);
     }
-    DISPATCHER.dispatch(text_editor_proxy, |ime| {
+    DISPATCHER.dispatch("delete_forward", text_editor_proxy, |ime| {
         ime.delete_forward(length.max(0) as usize);
     });
 }
@@ -187,7 +184,7 @@ pub extern "C" fn delete_backward(
     if length < 0 {
         log::warn!("delete_backward called with negative length: {}", length);
     }
-    DISPATCHER.dispatch(text_editor_proxy, |ime| {
+    DISPATCHER.dispatch("delete_backward", text_editor_proxy, |ime| {
         ime.delete_backward(length.max(0) as usize);
     });
 }
@@ -197,15 +194,18 @@ pub extern "C" fn send_keyboard_status(
     keyboard_status: InputMethod_KeyboardStatus,
 ) {
     let status = KeyboardStatus::from(keyboard_status);
-    DISPATCHER.dispatch(text_editor_proxy, |ime| ime.keyboard_status_changed(status));
+    DISPATCHER.dispatch("send_keyboard_status", text_editor_proxy, |ime| {
+        ime.keyboard_status_changed(status)
+    });
 }
 
 pub extern "C" fn send_enter_key(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     enter_key_type: InputMethod_EnterKeyType,
 ) {
-    DISPATCHER.dispatch(text_editor_proxy, |ime| {
-        ime.send_enter_key(enter_key_type);
+    let enter_key = EnterKeyType::from(enter_key_type);
+    DISPATCHER.dispatch("send_enter_key", text_editor_proxy, |ime| {
+        ime.send_enter_key(enter_key);
     });
 }
 
@@ -213,7 +213,10 @@ pub extern "C" fn move_cursor(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     direction: InputMethod_Direction,
 ) {
-    error!("move_cursor not implemented");
+    let direction = CursorDirection::from(direction);
+    DISPATCHER.dispatch("move_cursor", text_editor_proxy, |ime| {
+        ime.move_cursor(direction)
+    });
 }
 
 pub extern "C" fn handle_set_selection(
@@ -221,63 +224,203 @@ pub extern "C" fn handle_set_selection(
     start: i32,
     end: i32,
 ) {
-    error!("handle_text_selection not implemented");
+    let Ok(selection) = TextSelection::from_raw(start, end) else {
+        error!("IME `handle_set_selection` received invalid selection range: {start}..{end}");
+        return;
+    };
+    DISPATCHER.dispatch("handle_set_selection", text_editor_proxy, |ime| {
+        ime.set_selection(selection)
+    });
 }
 
 pub extern "C" fn handle_extend_action(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     action: InputMethod_ExtendAction,
 ) {
-    error!("handle_extend_action not implemented");
+    let action = ExtendAction::from(action);
+    DISPATCHER.dispatch("handle_extend_action", text_editor_proxy, |ime| {
+        ime.handle_extend_action(action)
+    });
 }
 
-pub extern "C" fn get_left_text_of_cursor(
+/// Which end of the text to keep when truncating surrounding text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum KeepEnd {
+    /// Keep the beginning of the text, i.e. truncate at the end.
+    Start,
+    /// Keep the end of the text, i.e. truncate at the beginning.
+    End,
+}
+
+/// Encode `text` as utf-16, keeping at most `max_len` code units.
+///
+/// Surrogate pairs are never split, so the result may be one unit shorter than `max_len`.
+fn truncated_utf16(text: &str, max_len: usize, keep: KeepEnd) -> Vec<u16> {
+    let mut utf16: Vec<u16> = text.encode_utf16().collect();
+    if utf16.len() <= max_len {
+        return utf16;
+    }
+    let is_high_surrogate = |unit: u16| (0xD800..=0xDBFF).contains(&unit);
+    let is_low_surrogate = |unit: u16| (0xDC00..=0xDFFF).contains(&unit);
+    match keep {
+        KeepEnd::Start => {
+            utf16.truncate(max_len);
+            if utf16.last().is_some_and(|&unit| is_high_surrogate(unit)) {
+                utf16.pop();
+            }
+        }
+        KeepEnd::End => {
+            let mut start = utf16.len() - max_len;
+            if utf16.get(start).is_some_and(|&unit| is_low_surrogate(unit)) {
+                start += 1;
+            }
+            utf16.drain(..start);
+        }
+    }
+    utf16
+}
+
+/// Write the text returned by `f` into the buffer provided by the input method.
+///
+/// `length` initially contains the capacity of `text` and is updated with the number of
+/// utf-16 code units written.
+fn write_surrounding_text(
+    caller: &'static str,
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     number: i32,
     text: *mut u16,
     length: *mut usize,
+    keep: KeepEnd,
+    f: impl FnOnce(&dyn Ime, usize) -> String,
 ) {
-    error!("get_left_text_of_cursor not implemented");
+    if text.is_null() || length.is_null() {
+        error!("IME `{caller}` called with null pointer: text {text:?} length {length:?}");
+        return;
+    }
+    #[cfg(debug_assertions)]
+    if number < 0 {
+        warn!("{caller} called with negative number: {number}");
+    }
+    let number = number.max(0) as usize;
+    // SAFETY: We checked for null above and trust the OH APIs to pass a valid pointer.
+    let capacity = unsafe { *length };
+    let mut written = 0;
+    DISPATCHER.dispatch(caller, text_editor_proxy, |ime| {
+        let rust_text = f(ime, number);
+        let utf16 = truncated_utf16(&rust_text, capacity.min(number), keep);
+        // SAFETY: The OH APIs guarantee that `text` points to a buffer with at least `capacity`
+        // elements, and `utf16.len() <= capacity`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(utf16.as_ptr(), text, utf16.len());
+        }
+        written = utf16.len();
+    });
+    // SAFETY: We checked for null above.
     unsafe {
-        *text = 0;
-        *length = 1;
+        *length = written;
     }
 }
 
+pub extern "C" fn get_left_text_of_cursor(
+    text_editor_proxy: *mut InputMethod_TextEditorProxy,
+    number: i32,
+    text: *mut u16,
+    length: *mut usize,
+) {
+    write_surrounding_text(
+        "get_left_text_of_cursor",
+        text_editor_proxy,
+        number,
+        text,
+        length,
+        KeepEnd::End,
+        |ime, n| ime.text_before_cursor(n),
+    );
+}
+
 pub extern "C" fn get_right_text_of_cursor(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     number: i32,
     text: *mut u16,
     length: *mut usize,
 ) {
-    error!("get_right_text_of_cursor not implemented");
-    unsafe {
-        *text = 0;
-        *length = 1;
-    }
+    write_surrounding_text(
+        "get_right_text_of_cursor",
+        text_editor_proxy,
+        number,
+        text,
+        length,
+        KeepEnd::Start,
+        |ime, n| ime.text_after_cursor(n),
+    );
 }
 
 pub extern "C" fn get_text_index_at_cursor(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
 ) -> i32 {
-    error!("get_text_index_at_cursor stubbed");
-    0
+    let mut index = 0;
+    DISPATCHER.dispatch("get_text_index_at_cursor", text_editor_proxy, |ime| {
+        let cursor = ime.cursor_index();
+        index = i32::try_from(cursor).unwrap_or_else(|_| {
+            warn!("IME cursor index {cursor} exceeds i32::MAX, clamping");
+            i32::MAX
+        });
+    });
+    index
 }
 
+/// Return value of `receive_private_command` signaling success.
+const PRIVATE_COMMAND_OK: i32 = 0;
+/// Return value of `receive_private_command` signaling failure.
+const PRIVATE_COMMAND_ERROR: i32 = -1;
+
 pub extern "C" fn receive_private_command(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     private_command: *mut *mut InputMethod_PrivateCommand,
     size: usize,
 ) -> i32 {
-    error!("receive_private_command not implemented");
-    if !private_command.is_null() {
-        unsafe {
-            *private_command = core::ptr::null_mut();
+    let raw_commands: &[*mut InputMethod_PrivateCommand] = if size == 0 {
+        &[]
+    } else if private_command.is_null() {
+        error!("receive_private_command received {size} commands, but a null pointer");
+        return PRIVATE_COMMAND_ERROR;
+    } else {
+        // SAFETY: We trust the OH APIs to give us a valid array of `size` commands.
+        unsafe { core::slice::from_raw_parts(private_command, size) }
+    };
+    let commands: Result<Vec<_>, _> = raw_commands
+        .iter()
+        .map(|&raw| {
+            let raw = NonNull::new(raw).ok_or(PrivateCommandError::InvalidCommand)?;
+            // SAFETY: We trust the OH APIs to give us valid commands.
+            unsafe { PrivateCommand::from_raw(raw) }
+        })
+        .collect();
+    let commands = match commands {
+        Ok(commands) => commands,
+        Err(e) => {
+            error!("IME `receive_private_command` failed to convert commands: {e:?}");
+            return PRIVATE_COMMAND_ERROR;
         }
-    }
-    0
+    };
+    let mut result = PRIVATE_COMMAND_ERROR;
+    DISPATCHER.dispatch("receive_private_command", text_editor_proxy, |ime| {
+        result = match ime.receive_private_commands(&commands) {
+            Ok(()) => PRIVATE_COMMAND_OK,
+            Err(e) => {
+                debug!("Ime rejected private commands: {e:?}");
+                PRIVATE_COMMAND_ERROR
+            }
+        };
+    });
+    result
 }
 
+/// Return value of `set_preview_text` signaling success.
+const PREVIEW_TEXT_OK: i32 = 0;
+/// Return value of `set_preview_text` signaling invalid parameters.
+const PREVIEW_TEXT_INVALID: i32 = -1;
+
 pub extern "C" fn set_preview_text(
     text_editor_proxy: *mut InputMethod_TextEditorProxy,
     text: *const u16,
@@ -285,10 +428,343 @@ pub extern "C" fn set_preview_text(
     start: i32,
     end: i32,
 ) -> i32 {
-    error!("set_preview_text not implemented");
-    0
+    // The input method passes `-1` for both indices if the preview text has no explicit range.
+    let range = if start == -1 && end == -1 {
+        None
+    } else {
+        match TextSelection::from_raw(start, end) {
+            Ok(range) => Some(range),
+            Err(_) => {
+                error!("IME `set_preview_text` received invalid range: {start}..{end}");
+                return PREVIEW_TEXT_INVALID;
+            }
+        }
+    };
+    let preview_text = if length == 0 {
+        String::new()
+    } else {
+        let utf16_str = slice_from_raw_parts(text, length);
+        // SAFETY: We trust the OH APIs to give us a valid u16 slice
+        let Some(slice) = (unsafe { utf16_str.as_ref() }) else {
+            error!("set_preview_text received text slice with len {length} but addr {text:?}");
+            return PREVIEW_TEXT_INVALID;
+        };
+        match String::from_utf16(slice) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("IME `set_preview_text` received malformed utf-16 string: {e:?}");
+                return PREVIEW_TEXT_INVALID;
+            }
+        }
+    };
+    // Stays invalid if there is no usable `Ime` or it panics.
+    let mut result = PREVIEW_TEXT_INVALID;
+    DISPATCHER.dispatch("set_preview_text", text_editor_proxy, |ime| {
+        ime.set_preview_text(preview_text, range);
+        result = PREVIEW_TEXT_OK;
+    });
+    result
 }
 
 pub extern "C" fn finish_text_preview(text_editor_proxy: *mut InputMethod_TextEditorProxy) {
-    error!("finish_text_preview not implemented");
+    DISPATCHER.dispatch("finish_text_preview", text_editor_proxy, |ime| {
+        ime.finish_preview_text()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::private_command::{FakeCommandValue, FakePrivateCommand};
+    use crate::ffi::text_avoid_info::FakeTextAvoidInfo;
+    use crate::ffi::text_config::FakeTextConfig;
+    use crate::ffi::text_editor_proxy::OH_TextEditorProxy_Create;
+    use crate::test_utils::{Attached, Event, RecordingIme};
+    use crate::{PrivateCommandValue, TextInputType};
+    use ohos_ime_sys::types::InputMethod_TextInputType;
+
+    fn editor_ptr(attached: &Attached) -> *mut InputMethod_TextEditorProxy {
+        attached.proxy.editor.raw.as_ptr()
+    }
+
+    #[test]
+    fn insert_text_decodes_utf16() {
+        let attached = Attached::new(RecordingIme::default());
+        let text: Vec<u16> = "hä😀".encode_utf16().collect();
+        let insert_text = attached.callbacks().insert_text.unwrap();
+        unsafe { insert_text(editor_ptr(&attached), text.as_ptr(), text.len()) };
+        assert_eq!(
+            attached.take_events(),
+            vec![Event::InsertText("hä😀".to_string())]
+        );
+    }
+
+    #[test]
+    fn insert_text_rejects_malformed_utf16() {
+        let attached = Attached::new(RecordingIme::default());
+        let text = [0xD800_u16];
+        let insert_text = attached.callbacks().insert_text.unwrap();
+        unsafe { insert_text(editor_ptr(&attached), text.as_ptr(), text.len()) };
+        assert_eq!(attached.take_events(), vec![]);
+    }
+
+    #[test]
+    fn delete_clamps_negative_length() {
+        let attached = Attached::new(RecordingIme::default());
+        let callbacks = attached.callbacks();
+        unsafe {
+            callbacks.delete_forward.unwrap()(editor_ptr(&attached), 2);
+            callbacks.delete_backward.unwrap()(editor_ptr(&attached), -1);
+        }
+        assert_eq!(
+            attached.take_events(),
+            vec![Event::DeleteForward(2), Event::DeleteBackward(0)]
+        );
+    }
+
+    #[test]
+    fn typed_events_are_converted() {
+        let attached = Attached::new(RecordingIme::default());
+        let callbacks = attached.callbacks();
+        let editor = editor_ptr(&attached);
+        unsafe {
+            callbacks.move_cursor.unwrap()(editor, InputMethod_Direction::IME_DIRECTION_LEFT);
+            callbacks.move_cursor.unwrap()(editor, InputMethod_Direction(42));
+            callbacks.handle_extend_action.unwrap()(
+                editor,
+                InputMethod_ExtendAction::IME_EXTEND_ACTION_PASTE,
+            );
+            callbacks.send_keyboard_status.unwrap()(
+                editor,
+                InputMethod_KeyboardStatus::IME_KEYBOARD_STATUS_SHOW,
+            );
+        }
+        assert_eq!(
+            attached.take_events(),
+            vec![
+                Event::MoveCursor(CursorDirection::Left),
+                Event::MoveCursor(CursorDirection::Unknown(42)),
+                Event::ExtendAction(ExtendAction::Paste),
+                Event::KeyboardStatusChanged(KeyboardStatus::Shown),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_selection_rejects_invalid_ranges() {
+        let attached = Attached::new(RecordingIme::default());
+        let handle_set_selection = attached.callbacks().handle_set_selection.unwrap();
+        unsafe {
+            handle_set_selection(editor_ptr(&attached), 1, 3);
+            handle_set_selection(editor_ptr(&attached), -1, 3);
+            handle_set_selection(editor_ptr(&attached), 3, 1);
+        }
+        assert_eq!(
+            attached.take_events(),
+            vec![Event::SetSelection(TextSelection::new(1, 3).unwrap())]
+        );
+    }
+
+    fn call_surrounding_text(
+        attached: &Attached,
+        left: bool,
+        number: i32,
+        capacity: usize,
+    ) -> String {
+        let callbacks = attached.callbacks();
+        let f = if left {
+            callbacks.get_left_text_of_cursor.unwrap()
+        } else {
+            callbacks.get_right_text_of_cursor.unwrap()
+        };
+        let mut buf = vec![0_u16; capacity];
+        let mut length = capacity;
+        unsafe {
+            f(
+                editor_ptr(attached),
+                number,
+                buf.as_mut_ptr(),
+                &raw mut length,
+            )
+        };
+        assert!(length <= capacity);
+        String::from_utf16(&buf[..length]).unwrap()
+    }
+
+    #[test]
+    fn surrounding_text_is_truncated_without_splitting_surrogates() {
+        let attached = Attached::new(RecordingIme {
+            text_before_cursor: "ab😀cd".to_string(),
+            text_after_cursor: "ef😀gh".to_string(),
+            ..RecordingIme::default()
+        });
+        assert_eq!(call_surrounding_text(&attached, true, 10, 10), "ab😀cd");
+        assert_eq!(call_surrounding_text(&attached, true, 10, 4), "😀cd");
+        assert_eq!(call_surrounding_text(&attached, true, 3, 10), "cd");
+        assert_eq!(call_surrounding_text(&attached, false, 10, 3), "ef");
+        assert_eq!(call_surrounding_text(&attached, false, 4, 10), "ef😀");
+        assert_eq!(call_surrounding_text(&attached, false, -1, 10), "");
+    }
+
+    #[test]
+    fn cursor_index_is_clamped() {
+        let attached = Attached::new(RecordingIme {
+            cursor_index: 7,
+            ..RecordingIme::default()
+        });
+        let get_index = attached.callbacks().get_text_index_at_cursor.unwrap();
+        assert_eq!(unsafe { get_index(editor_ptr(&attached)) }, 7);
+
+        let attached = Attached::new(RecordingIme {
+            cursor_index: usize::MAX,
+            ..RecordingIme::default()
+        });
+        assert_eq!(unsafe { get_index(editor_ptr(&attached)) }, i32::MAX);
+    }
+
+    #[test]
+    fn preview_text() {
+        let attached = Attached::new(RecordingIme::default());
+        let callbacks = attached.callbacks();
+        let editor = editor_ptr(&attached);
+        let text: Vec<u16> = "nǐ".encode_utf16().collect();
+        unsafe {
+            let set_preview_text = callbacks.set_preview_text.unwrap();
+            assert_eq!(
+                set_preview_text(editor, text.as_ptr(), text.len(), -1, -1),
+                0
+            );
+            assert_eq!(set_preview_text(editor, text.as_ptr(), text.len(), 0, 2), 0);
+            assert_ne!(set_preview_text(editor, text.as_ptr(), text.len(), 2, 0), 0);
+            callbacks.finish_text_preview.unwrap()(editor);
+        }
+        assert_eq!(
+            attached.take_events(),
+            vec![
+                Event::SetPreviewText("nǐ".to_string(), None),
+                Event::SetPreviewText("nǐ".to_string(), Some(TextSelection::new(0, 2).unwrap())),
+                Event::FinishPreviewText,
+            ]
+        );
+    }
+
+    #[test]
+    fn receive_private_commands() {
+        let attached = Attached::new(RecordingIme::default());
+        let raw_commands = [
+            FakePrivateCommand {
+                key: b"flag".to_vec(),
+                value: FakeCommandValue::Bool(true),
+            },
+            FakePrivateCommand {
+                key: b"name".to_vec(),
+                value: FakeCommandValue::Str("wörld".as_bytes().to_vec()),
+            },
+        ];
+        let mut raw_ptrs: Vec<_> = raw_commands.iter().map(|c| c.to_raw()).collect();
+        let receive = attached.callbacks().receive_private_command.unwrap();
+        let res = unsafe { receive(editor_ptr(&attached), raw_ptrs.as_mut_ptr(), raw_ptrs.len()) };
+        assert_eq!(res, 0);
+        assert_eq!(
+            attached.take_events(),
+            vec![Event::PrivateCommands(vec![
+                PrivateCommand::new("flag", PrivateCommandValue::Bool(true)),
+                PrivateCommand::new("name", PrivateCommandValue::String("wörld".to_string())),
+            ])]
+        );
+        for ptr in raw_ptrs {
+            unsafe { crate::ffi::private_command::OH_PrivateCommand_Destroy(ptr) };
+        }
+    }
+
+    #[test]
+    fn text_config_is_applied_on_attach() {
+        let config = TextConfigBuilder::new()
+            .input_type(TextInputType::Number)
+            .enterkey_type(EnterKeyType::Done)
+            .preview_text_support(true)
+            .selection(TextSelection::new(1, 2).unwrap())
+            .window_id(5)
+            .avoid_info(300.0, 48.0)
+            .build();
+        let attached = Attached::new(RecordingIme {
+            config,
+            ..RecordingIme::default()
+        });
+        // SAFETY: The proxy is attached.
+        let fake =
+            unsafe { crate::ffi::inputmethod_proxy::fake_proxy(attached.proxy.raw.as_ptr()) };
+        assert_eq!(
+            fake.text_config,
+            FakeTextConfig {
+                input_type: InputMethod_TextInputType::IME_TEXT_INPUT_TYPE_NUMBER,
+                enter_key_type: InputMethod_EnterKeyType::IME_ENTER_KEY_DONE,
+                preview_text_support: true,
+                selection: (1, 2),
+                window_id: 5,
+                avoid_info: FakeTextAvoidInfo {
+                    position_y: 300.0,
+                    height: 48.0,
+                },
+                placeholder: String::new(),
+                ability_name: String::new(),
+            }
+        );
+    }
+
+    #[cfg(feature = "api-20")]
+    #[test]
+    fn placeholder_and_ability_name_are_applied() {
+        let long_name = "x".repeat(200);
+        let config = TextConfigBuilder::new()
+            .placeholder("Search 🔍")
+            .ability_name(&long_name)
+            .build();
+        let attached = Attached::new(RecordingIme {
+            config,
+            ..RecordingIme::default()
+        });
+        // SAFETY: The proxy is attached.
+        let fake =
+            unsafe { crate::ffi::inputmethod_proxy::fake_proxy(attached.proxy.raw.as_ptr()) };
+        assert_eq!(fake.text_config.placeholder, "Search 🔍");
+        assert_eq!(fake.text_config.ability_name, long_name[..127]);
+    }
+
+    #[test]
+    fn dispatch_to_unknown_proxy_is_ignored() {
+        let unknown = unsafe { OH_TextEditorProxy_Create() };
+        delete_forward(unknown, 1);
+        assert_eq!(get_text_index_at_cursor(unknown), 0);
+        let text = [u16::from(b'a')];
+        assert_eq!(
+            set_preview_text(unknown, text.as_ptr(), text.len(), -1, -1),
+            PREVIEW_TEXT_INVALID
+        );
+        unsafe { crate::ffi::text_editor_proxy::OH_TextEditorProxy_Destroy(unknown) };
+    }
+
+    #[test]
+    fn config_enums_round_trip() {
+        for raw in -2..15 {
+            let input_type = TextInputType::from(InputMethod_TextInputType(raw));
+            assert_eq!(InputMethod_TextInputType::from(input_type).0, raw);
+        }
+        assert_eq!(
+            TextInputType::from(InputMethod_TextInputType(13)),
+            TextInputType::OneTimeCode
+        );
+        assert_eq!(
+            TextInputType::from(InputMethod_TextInputType(14)),
+            TextInputType::Unknown(14)
+        );
+        for raw in 0..10 {
+            let enter_key = EnterKeyType::from(InputMethod_EnterKeyType(raw));
+            assert_eq!(InputMethod_EnterKeyType::from(enter_key).0, raw);
+        }
+        assert_eq!(
+            EnterKeyType::from(InputMethod_EnterKeyType::IME_ENTER_KEY_NEWLINE),
+            EnterKeyType::Newline
+        );
+    }
 }
@@ -0,0 +1,322 @@
+//! Conversion between the different ways to index into text.
+//!
+//! The inputmethod API uses utf-16 code unit indices, since that is what ArkTS strings use,
+//! while Rust strings are indexed by utf-8 byte offsets.
+
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// An index could not be converted, see [`TextSelection::from_utf8_range()`] and friends.
+///
+/// [`TextSelection::from_utf8_range()`]: crate::TextSelection::from_utf8_range
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IndexError {
+    /// The index is past the end of the text.
+    OutOfBounds,
+    /// The index points into the middle of a code point, e.g. between the two halves of a utf-16
+    /// surrogate pair.
+    SplitsCodePoint,
+    /// The index points into the middle of a grapheme cluster.
+    SplitsGrapheme,
+    /// The start of the range is after the end.
+    InvertedRange,
+    /// The index is too large to be passed to the inputmethod API.
+    TooLarge,
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IndexError::OutOfBounds => "index out of bounds",
+            IndexError::SplitsCodePoint => "index splits a code point",
+            IndexError::SplitsGrapheme => "index splits a grapheme cluster",
+            IndexError::InvertedRange => "range start is after the end",
+            IndexError::TooLarge => "index too large for the inputmethod API",
+        })
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+fn check_char_boundary(text: &str, offset: usize) -> Result<(), IndexError> {
+    if offset > text.len() {
+        Err(IndexError::OutOfBounds)
+    } else if !text.is_char_boundary(offset) {
+        Err(IndexError::SplitsCodePoint)
+    } else {
+        Ok(())
+    }
+}
+
+/// Convert a byte offset into a utf-16 index.
+pub(crate) fn utf8_to_utf16(text: &str, offset: usize) -> Result<usize, IndexError> {
+    check_char_boundary(text, offset)?;
+    Ok(text[..offset].encode_utf16().count())
+}
+
+/// Convert a utf-16 index into a byte offset.
+pub(crate) fn utf16_to_utf8(text: &str, index: usize) -> Result<usize, IndexError> {
+    let mut utf16_count = 0;
+    for (offset, c) in text.char_indices() {
+        if utf16_count == index {
+            return Ok(offset);
+        }
+        utf16_count += c.len_utf16();
+        if utf16_count > index {
+            return Err(IndexError::SplitsCodePoint);
+        }
+    }
+    if utf16_count == index {
+        Ok(text.len())
+    } else {
+        Err(IndexError::OutOfBounds)
+    }
+}
+
+/// Convert a utf-16 index into a byte offset, rounding down to a `char` boundary and clamping it
+/// to `text.len()`.
+pub(crate) fn utf16_to_utf8_floor(text: &str, index: usize) -> usize {
+    match utf16_to_utf8(text, index) {
+        Ok(offset) => offset,
+        // Only an index between the two halves of a surrogate pair splits a code point.
+        Err(IndexError::SplitsCodePoint) => utf16_to_utf8(text, index - 1).unwrap_or(text.len()),
+        Err(_) => text.len(),
+    }
+}
+
+/// Convert a byte offset into a `char` index.
+pub(crate) fn utf8_to_char(text: &str, offset: usize) -> Result<usize, IndexError> {
+    check_char_boundary(text, offset)?;
+    Ok(text[..offset].chars().count())
+}
+
+/// Convert a `char` index into a byte offset.
+pub(crate) fn char_to_utf8(text: &str, index: usize) -> Result<usize, IndexError> {
+    text.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .nth(index)
+        .ok_or(IndexError::OutOfBounds)
+}
+
+/// Convert a byte offset into a grapheme cluster index.
+pub(crate) fn utf8_to_grapheme(text: &str, offset: usize) -> Result<usize, IndexError> {
+    check_char_boundary(text, offset)?;
+    if offset == text.len() {
+        return Ok(text.graphemes(true).count());
+    }
+    text.grapheme_indices(true)
+        .position(|(start, _)| start == offset)
+        .ok_or(IndexError::SplitsGrapheme)
+}
+
+/// Convert a grapheme cluster index into a byte offset.
+pub(crate) fn grapheme_to_utf8(text: &str, index: usize) -> Result<usize, IndexError> {
+    text.grapheme_indices(true)
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .nth(index)
+        .ok_or(IndexError::OutOfBounds)
+}
+
+/// Encode `text` as a NUL terminated utf-16 string of at most `max_len` code units (including the
+/// NUL), as expected by the inputmethod API. Longer text is truncated at a code point boundary.
+#[cfg(feature = "api-20")]
+pub(crate) fn to_utf16_with_nul(text: &str, max_len: usize) -> Vec<u16> {
+    let mut utf16 = Vec::with_capacity(text.len().min(max_len) + 1);
+    let mut buf = [0; 2];
+    for c in text.chars() {
+        let encoded = c.encode_utf16(&mut buf);
+        if utf16.len() + encoded.len() >= max_len {
+            break;
+        }
+        utf16.extend_from_slice(encoded);
+    }
+    utf16.push(0);
+    utf16
+}
+
+/// The byte range of `text` to delete for [`Ime::delete_backward()`](crate::Ime::delete_backward).
+///
+/// `cursor` is the byte offset of the cursor and `len` the number of utf-16 code units to delete,
+/// as requested by the input method. The range is extended to whole grapheme clusters, so that
+/// surrogate pairs, emoji ZWJ sequences and characters with combining marks are deleted completely.
+pub fn delete_backward_range(
+    text: &str,
+    cursor: usize,
+    len: usize,
+) -> Result<Range<usize>, IndexError> {
+    check_char_boundary(text, cursor)?;
+    let mut remaining = len;
+    let mut start = cursor;
+    for grapheme in text[..cursor].graphemes(true).rev() {
+        if remaining == 0 {
+            break;
+        }
+        remaining = remaining.saturating_sub(grapheme.encode_utf16().count());
+        start -= grapheme.len();
+    }
+    Ok(start..cursor)
+}
+
+/// The byte range of `text` to delete for [`Ime::delete_forward()`](crate::Ime::delete_forward).
+///
+/// See [`delete_backward_range()`].
+pub fn delete_forward_range(
+    text: &str,
+    cursor: usize,
+    len: usize,
+) -> Result<Range<usize>, IndexError> {
+    check_char_boundary(text, cursor)?;
+    let mut remaining = len;
+    let mut end = cursor;
+    for grapheme in text[cursor..].graphemes(true) {
+        if remaining == 0 {
+            break;
+        }
+        remaining = remaining.saturating_sub(grapheme.encode_utf16().count());
+        end += grapheme.len();
+    }
+    Ok(cursor..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextSelection;
+
+    // "e" + combining acute accent, a family emoji (ZWJ sequence) and a surrogate pair.
+    const TEXT: &str = "ae\u{301}👨‍👩‍👧b😀";
+
+    #[test]
+    fn utf16() {
+        assert_eq!(utf8_to_utf16(TEXT, 0), Ok(0));
+        assert_eq!(utf8_to_utf16(TEXT, 4), Ok(3));
+        assert_eq!(utf8_to_utf16(TEXT, 8), Ok(5));
+        assert_eq!(utf8_to_utf16(TEXT, 5), Err(IndexError::SplitsCodePoint));
+        assert_eq!(utf8_to_utf16(TEXT, TEXT.len()), Ok(14));
+        assert_eq!(utf8_to_utf16(TEXT, 100), Err(IndexError::OutOfBounds));
+        assert_eq!(utf16_to_utf8(TEXT, 3), Ok(4));
+        assert_eq!(utf16_to_utf8(TEXT, 4), Err(IndexError::SplitsCodePoint));
+        assert_eq!(utf16_to_utf8(TEXT, 5), Ok(8));
+        assert_eq!(utf16_to_utf8(TEXT, 14), Ok(TEXT.len()));
+        assert_eq!(utf16_to_utf8(TEXT, 15), Err(IndexError::OutOfBounds));
+        assert_eq!(utf16_to_utf8_floor(TEXT, 4), 4);
+        assert_eq!(utf16_to_utf8_floor(TEXT, 13), TEXT.len() - 4);
+        assert_eq!(utf16_to_utf8_floor(TEXT, 15), TEXT.len());
+    }
+
+    #[test]
+    fn chars() {
+        assert_eq!(utf8_to_char(TEXT, 4), Ok(3));
+        assert_eq!(utf8_to_char(TEXT, 5), Err(IndexError::SplitsCodePoint));
+        assert_eq!(char_to_utf8(TEXT, 3), Ok(4));
+        assert_eq!(char_to_utf8(TEXT, 10), Ok(TEXT.len()));
+        assert_eq!(char_to_utf8(TEXT, 11), Err(IndexError::OutOfBounds));
+    }
+
+    #[test]
+    fn graphemes() {
+        assert_eq!(utf8_to_grapheme(TEXT, 1), Ok(1));
+        assert_eq!(utf8_to_grapheme(TEXT, 2), Err(IndexError::SplitsGrapheme));
+        assert_eq!(utf8_to_grapheme(TEXT, 4), Ok(2));
+        assert_eq!(utf8_to_grapheme(TEXT, 8), Err(IndexError::SplitsGrapheme));
+        assert_eq!(utf8_to_grapheme(TEXT, TEXT.len()), Ok(5));
+        assert_eq!(grapheme_to_utf8(TEXT, 3), Ok(TEXT.len() - 5));
+        assert_eq!(grapheme_to_utf8(TEXT, 5), Ok(TEXT.len()));
+        assert_eq!(grapheme_to_utf8(TEXT, 6), Err(IndexError::OutOfBounds));
+    }
+
+    #[test]
+    fn text_selection_conversions() {
+        let emoji = TEXT.len() - 4..TEXT.len();
+        let selection = TextSelection::from_utf8_range(TEXT, emoji.clone()).unwrap();
+        assert_eq!((selection.start(), selection.end()), (12, 14));
+        assert_eq!(selection.to_utf8_range(TEXT), Ok(emoji));
+        assert_eq!(selection.to_char_range(TEXT), Ok(9..10));
+        assert_eq!(selection.to_grapheme_range(TEXT), Ok(4..5));
+        assert_eq!(
+            TextSelection::from_char_range(TEXT, 9..10),
+            Ok(selection.clone())
+        );
+        assert_eq!(
+            TextSelection::from_grapheme_range(TEXT, 4..5),
+            Ok(selection)
+        );
+
+        let family = TextSelection::from_grapheme_range(TEXT, 2..3).unwrap();
+        assert_eq!((family.start(), family.end()), (3, 11));
+        let half_emoji = TextSelection::new(12, 13).unwrap();
+        assert_eq!(
+            half_emoji.to_utf8_range(TEXT),
+            Err(IndexError::SplitsCodePoint)
+        );
+        let in_family = TextSelection::new(3, 5).unwrap();
+        assert_eq!(
+            in_family.to_grapheme_range(TEXT),
+            Err(IndexError::SplitsGrapheme)
+        );
+        assert_eq!(
+            TextSelection::from_utf8_range(TEXT, std::ops::Range { start: 4, end: 2 }),
+            Err(IndexError::InvertedRange)
+        );
+    }
+
+    #[cfg(feature = "api-20")]
+    #[test]
+    fn utf16_with_nul() {
+        assert_eq!(to_utf16_with_nul("", 4), [0]);
+        assert_eq!(to_utf16_with_nul("ab", 4), [0x61, 0x62, 0]);
+        assert_eq!(to_utf16_with_nul("abcd", 4), [0x61, 0x62, 0x63, 0]);
+        // The surrogate pair does not fit and is not split.
+        assert_eq!(to_utf16_with_nul("ab😀", 4), [0x61, 0x62, 0]);
+    }
+
+    #[test]
+    fn delete_ranges() {
+        // (text, cursor, len, backward range, forward range)
+        type Case = (&'static str, usize, usize, Range<usize>, Range<usize>);
+        let cases: &[Case] = &[
+            ("abc", 1, 1, 0..1, 1..2),
+            ("abc", 1, 5, 0..1, 1..3),
+            ("abc", 1, 0, 1..1, 1..1),
+            // Surrogate pair, the input method requests 2 code units.
+            ("a😀b", 5, 2, 1..5, 5..6),
+            ("a😀b", 1, 2, 0..1, 1..5),
+            // Only half of the surrogate pair is requested.
+            ("😀😀", 4, 1, 0..4, 4..8),
+            // Family emoji: 👨 ZWJ 👩 ZWJ 👧 are 8 code units and 18 bytes.
+            ("👨‍👩‍👧x", 18, 2, 0..18, 18..19),
+            ("x👨‍👩‍👧", 1, 1, 0..1, 1..19),
+            // Flag emoji, made of two regional indicators.
+            ("🇩🇪🇫🇷", 8, 2, 0..8, 8..16),
+            // "e" followed by a combining acute accent and "a" with a precomposed accent.
+            ("e\u{301}á", 3, 1, 0..3, 3..5),
+            ("e\u{301}á", 3, 2, 0..3, 3..5),
+            // Hangul syllable made of conjoining jamo.
+            ("\u{1100}\u{1161}\u{11A8}", 9, 1, 0..9, 9..9),
+        ];
+        for (text, cursor, len, backward, forward) in cases {
+            assert_eq!(
+                delete_backward_range(text, *cursor, *len).as_ref(),
+                Ok(backward),
+                "delete_backward({len}) in {text:?} at {cursor}"
+            );
+            assert_eq!(
+                delete_forward_range(text, *cursor, *len).as_ref(),
+                Ok(forward),
+                "delete_forward({len}) in {text:?} at {cursor}"
+            );
+        }
+        assert_eq!(
+            delete_backward_range("😀", 2, 1),
+            Err(IndexError::SplitsCodePoint)
+        );
+        assert_eq!(
+            delete_forward_range("a", 2, 1),
+            Err(IndexError::OutOfBounds)
+        );
+    }
+}